@@ -0,0 +1,43 @@
+//! Compares reading an environment variable with `raw_os` against `try_var`, which also
+//! validates it as UTF-8, as a script reading variables in a loop would.
+//!
+//! Run with `cargo bench --bench raw_os`.
+
+use qutescript::env::{raw_os, try_var};
+
+use std::env;
+use std::hint::black_box;
+use std::time::Instant;
+
+const KEY: &str = "QUTESCRIPT_BENCH_URL";
+const VALUE: &str = "https://example.com/some/fairly/long/path?with=a&query=string#and-fragment";
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<F: Fn() -> bool>(name: &str, f: F) {
+    // Warm up before timing.
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<8} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    env::set_var(KEY, VALUE);
+
+    bench("raw_os", || {
+        raw_os(black_box(KEY)).is_some_and(|value| value == VALUE)
+    });
+    bench("try_var", || {
+        try_var(black_box(KEY)).is_ok_and(|value| value == VALUE)
+    });
+}
@@ -0,0 +1,591 @@
+use crate::env;
+use crate::state;
+use crate::util::{self, escape_arg, mode_flag, send_command, Mode};
+use crate::yaml;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const CONFIG_STATE: &str = "config-state";
+
+/// Sets `option` to `value` for the duration of `body`.
+///
+/// This sends `set -t {option} {value}` before calling `body`, and
+/// `config-unset -t {option}` after it returns.
+///
+/// The value of `option` prior to the call can't be read back from qutebrowser (see
+/// [`Fifo`]). Instead of restoring it, the temporary setting is cleared, reverting
+/// `option` to the value from the user's configuration.
+///
+/// [`Fifo`]: ../env/struct.Fifo.html
+pub fn with_temp_setting<T, F>(option: &str, value: &str, body: F) -> Result<T, io::Error>
+where
+    F: FnOnce() -> T,
+{
+    set(option, value, true)?;
+    let ret = body();
+    unset(option, true)?;
+    Ok(ret)
+}
+
+/// Returns the value of `option` as set in the user's configuration files, or `None` if
+/// it isn't set in them, in which case it has its default value.
+///
+/// Settings can't be queried from qutebrowser (see [`Fifo`]). Instead, this reads
+/// `autoconfig.yml` and `config.py` in [`config_dir`] and applies qutebrowser's
+/// precedence rules:
+///
+/// - If there is no `config.py`, the value from `autoconfig.yml` is used.
+/// - Otherwise, the statements in `config.py` are applied in order, with
+///   `config.load_autoconfig()` applying the value from `autoconfig.yml` (unless called
+///   with `False`), and later statements overriding earlier ones.
+///
+/// Only the global value is considered, not values set for URL patterns. Settings in
+/// `config.py` are recognized in the forms `c.{option} = {value}` and
+/// `config.set('{option}', {value})` on a single line; Python strings are unquoted and
+/// booleans lowercased, and other values are returned as written. Values changed with
+/// `:set` are saved to `autoconfig.yml` and so are reflected, but temporary values set
+/// with `set -t` are not.
+///
+/// Lists and dicts in `autoconfig.yml` are stored as nested YAML, which has no single
+/// string form, so an error of kind `InvalidData` is returned if such a value is the one
+/// in effect.
+///
+/// [`Fifo`]: ../env/struct.Fifo.html
+/// [`config_dir`]: ../env/fn.config_dir.html
+pub fn effective_value(option: &str) -> Result<Option<String>, io::Error> {
+    let autoconfig_value = read_optional(&env::autoconfig_path())?
+        .and_then(|autoconfig| autoconfig_value(&autoconfig, option));
+    let config_py = match read_optional(&env::config_py_path())? {
+        Some(config_py) => config_py,
+        None => {
+            return autoconfig_value
+                .map(|value| scalar(option, value))
+                .transpose()
+        }
+    };
+
+    let mut value = None;
+    for line in config_py.lines() {
+        let line = strip_comment(line).trim();
+        if let Some(args) = line.strip_prefix("config.load_autoconfig(") {
+            if !args.trim_start().starts_with("False") && autoconfig_value.is_some() {
+                value = autoconfig_value.clone();
+            }
+        } else if let Some(assigned) = config_py_value(line, option) {
+            value = Some(yaml::Value::Scalar(assigned));
+        }
+    }
+    value.map(|value| scalar(option, value)).transpose()
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, io::Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn autoconfig_value(autoconfig: &str, option: &str) -> Option<yaml::Value> {
+    yaml::parse(autoconfig)
+        .get("settings")?
+        .get(option)?
+        .get("global")
+        .cloned()
+}
+
+fn scalar(option: &str, value: yaml::Value) -> Result<String, io::Error> {
+    match value {
+        yaml::Value::Scalar(value) => Ok(value),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has a list or dict value in autoconfig.yml", option),
+        )),
+    }
+}
+
+/// Returns the value assigned to `option` by a line of `config.py`, if any.
+fn config_py_value(line: &str, option: &str) -> Option<String> {
+    if let Some(assignment) = line.strip_prefix("c.") {
+        let (lhs, rhs) = assignment.split_once('=')?;
+        if lhs.trim() != option || rhs.starts_with('=') {
+            return None;
+        }
+        return Some(python_value(rhs));
+    }
+
+    let args = line.strip_prefix("config.set(")?.strip_suffix(')')?;
+    let args = split_args(args);
+    match args.as_slice() {
+        [name, value] if python_value(name) == option => Some(python_value(value)),
+        _ => None,
+    }
+}
+
+/// Splits Python call arguments on commas outside of strings and brackets.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    let mut chars = args.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                split.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&args[start..]);
+    split
+}
+
+/// Converts a Python literal to the string form qutebrowser uses for values.
+fn python_value(raw: &str) -> String {
+    let raw = raw.trim();
+    let quoted = raw.len() >= 2
+        && ((raw.starts_with('\'') && raw.ends_with('\''))
+            || (raw.starts_with('"') && raw.ends_with('"')));
+    if quoted {
+        let mut value = String::with_capacity(raw.len());
+        let mut chars = raw[1..raw.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next()),
+                c => value.push(c),
+            }
+        }
+        return value;
+    }
+
+    match raw {
+        "True" => "true".into(),
+        "False" => "false".into(),
+        raw => raw.into(),
+    }
+}
+
+/// Strips a trailing `#` comment from a line of Python.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The values of options set through [`set_and_remember`], persisted in a state file
+/// under [`data_dir`].
+///
+/// Settings can't be read back over the `FIFO`, so this lets a userscript act on values
+/// it set itself in earlier invocations. It only tracks values set through this type:
+/// changes made by the user, by other scripts, or with the other functions in this module
+/// aren't reflected, so the remembered value may be stale. See [`effective_value`] for
+/// reading the user's configuration files instead.
+///
+/// [`set_and_remember`]: #method.set_and_remember
+/// [`data_dir`]: ../env/fn.data_dir.html
+/// [`effective_value`]: ./fn.effective_value.html
+#[derive(Clone, Debug, Default)]
+pub struct ConfigState {
+    values: Vec<(String, String)>,
+}
+
+impl ConfigState {
+    /// Reads the remembered values, or returns an empty state if none have been saved.
+    pub fn load() -> Result<ConfigState, io::Error> {
+        let contents = state::read(CONFIG_STATE)?.unwrap_or_default();
+        let values = contents
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(option, value)| (option.into(), unescape_state(value)))
+            .collect();
+        Ok(ConfigState { values })
+    }
+
+    /// Returns the value last set for `option` through [`set_and_remember`], if any.
+    ///
+    /// [`set_and_remember`]: #method.set_and_remember
+    #[inline]
+    pub fn get(&self, option: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(o, _)| o == option)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets `option` to `value` (`set {option} {value}`) and remembers the value.
+    pub fn set_and_remember(&mut self, option: &str, value: &str) -> Result<(), io::Error> {
+        set(option, value, false)?;
+        match self.values.iter_mut().find(|(o, _)| o == option) {
+            Some((_, remembered)) => *remembered = value.into(),
+            None => self.values.push((option.into(), value.into())),
+        }
+        self.save()
+    }
+
+    /// Sends `cmd` if the remembered value of `option` is `equals`, returning whether it
+    /// was sent.
+    pub fn run_if(&self, option: &str, equals: &str, cmd: &str) -> Result<bool, io::Error> {
+        if self.get(option) != Some(equals) {
+            return Ok(false);
+        }
+        send_command(cmd)?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let contents: String = self
+            .values
+            .iter()
+            .map(|(option, value)| format!("{}\t{}\n", option, escape_state(value)))
+            .collect();
+        state::write(CONFIG_STATE, &contents)
+    }
+}
+
+fn escape_state(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_state(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Cycles `option` through `values`
+/// (`config-cycle [-u {pattern}] [-t] {option} {values...}`).
+///
+/// If `pattern` is given, only the value for URLs matching it is cycled, e.g. for the
+/// current site with [`url_to_domain_pattern`].
+///
+/// [`url_to_domain_pattern`]: ./fn.url_to_domain_pattern.html
+pub fn cycle(
+    option: &str,
+    values: &[&str],
+    pattern: Option<&str>,
+    temp: bool,
+) -> Result<(), io::Error> {
+    let mut message = String::from("config-cycle");
+    if let Some(pattern) = pattern {
+        message.push_str(" -u ");
+        message.push_str(&escape_arg(pattern));
+    }
+    message.push_str(temp_flag(temp));
+    message.push(' ');
+    message.push_str(&escape_arg(option));
+    for value in values {
+        message.push(' ');
+        message.push_str(&escape_arg(value));
+    }
+    send_command(&message)
+}
+
+/// Returns a URL pattern matching every page on the host of `url`, e.g.
+/// `*://example.com/*` for `https://example.com:8080/page`, or `None` if `url` has no
+/// host.
+///
+/// The pattern matches any scheme and port, but not subdomains of the host.
+pub fn url_to_domain_pattern(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = match host_port.strip_prefix('[') {
+        Some(ipv6) => &host_port[..ipv6.find(']')? + 2],
+        None => host_port.split(':').next()?,
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some(format!("*://{}/*", host))
+}
+
+/// Toggles the boolean `option` (`config-cycle [-t] {option} true false`).
+#[inline]
+pub fn toggle(option: &str, temp: bool) -> Result<(), io::Error> {
+    cycle(option, &["true", "false"], None, temp)
+}
+
+/// Sets each option to its value (`set [-t] {option} {value}`), writing all the commands
+/// to the `FIFO` at once.
+///
+/// The commands are batched (see [`begin_batch`]), so qutebrowser applies the settings
+/// together. If a batch was already started, the commands are added to it and are only
+/// written when it is flushed.
+///
+/// [`begin_batch`]: ../util/fn.begin_batch.html
+pub fn apply_settings(settings: &[(&str, &str)], temp: bool) -> Result<(), io::Error> {
+    let nested = util::is_batching();
+    util::begin_batch();
+    let ret = settings
+        .iter()
+        .try_for_each(|(option, value)| set(option, value, temp));
+    if nested {
+        return ret;
+    }
+
+    // End the batch even if a command failed, writing the commands collected so far.
+    let flushed = util::flush_batch();
+    ret.and(flushed)
+}
+
+fn set(option: &str, value: &str, temp: bool) -> Result<(), io::Error> {
+    let message = format!(
+        "set{} {} {}",
+        temp_flag(temp),
+        escape_arg(option),
+        escape_arg(value)
+    );
+    send_command(&message)
+}
+
+/// Reverts `option` to its default value (`config-unset [-t] {option}`).
+///
+/// If `temp` is `true`, only the temporary value set with `set -t` is cleared, reverting
+/// to the value from the user's configuration.
+pub fn unset(option: &str, temp: bool) -> Result<(), io::Error> {
+    let message = format!("config-unset{} {}", temp_flag(temp), escape_arg(option));
+    send_command(&message)
+}
+
+/// Binds `key` to `command` in the given mode, or normal mode if `mode` is `None`
+/// (`bind [--mode {mode}] {key} {command}`).
+///
+/// qutebrowser saves bindings made with `bind` to `autoconfig.yml`, and unlike settings,
+/// bindings can't be changed temporarily, so a userscript that sets up its own bindings
+/// only needs to do so once. `command` is taken as the rest of the command line, so
+/// newlines in it are replaced with spaces. `bind` is one of the commands qutebrowser
+/// doesn't split on `;;`, so `command` can chain several commands with it, as in
+/// `config.py`.
+pub fn bind(key: &str, command: &str, mode: Option<Mode>) -> Result<(), io::Error> {
+    let message = format!(
+        "bind{} {} {}",
+        mode_flag(mode),
+        escape_arg(key),
+        command.replace(['\n', '\r'], " ")
+    );
+    send_command(&message)
+}
+
+/// Unbinds `key` in the given mode, or normal mode if `mode` is `None`
+/// (`unbind [--mode {mode}] {key}`).
+pub fn unbind(key: &str, mode: Option<Mode>) -> Result<(), io::Error> {
+    let message = format!("unbind{} {}", mode_flag(mode), escape_arg(key));
+    send_command(&message)
+}
+
+/// Writes the user's settings to a `config.py` file
+/// (`config-write-py [--defaults] [--force] [{path}]`).
+///
+/// If `path` is `None`, qutebrowser writes to `config.py` in [`config_dir`]. If
+/// `defaults` is `true`, the default values of all settings are written instead of the
+/// user's settings. qutebrowser refuses to overwrite an existing file unless `force` is
+/// `true`.
+///
+/// [`config_dir`]: ../env/fn.config_dir.html
+pub fn write_py(path: Option<&Path>, defaults: bool, force: bool) -> Result<(), io::Error> {
+    let mut message = String::from("config-write-py");
+    if defaults {
+        message.push_str(" --defaults");
+    }
+    if force {
+        message.push_str(" --force");
+    }
+    if let Some(path) = path {
+        message.push(' ');
+        message.push_str(&escape_arg(&path.to_string_lossy()));
+    }
+    send_command(&message)
+}
+
+#[inline]
+fn temp_flag(temp: bool) -> &'static str {
+    if temp {
+        " -t"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn effective_value_rejects_list_values_from_autoconfig() {
+        let dir = testing::temp_dir("config-effective-value");
+        fs::write(
+            dir.join("autoconfig.yml"),
+            "config_version: 2\n\
+             settings:\n  \
+               content.blocking.whitelist:\n    \
+                 global:\n    \
+                 - example.com\n  \
+               content.javascript.enabled:\n    \
+                 global: false\n",
+        )
+        .unwrap();
+        let _env = testing::set_env(&[("QUTE_CONFIG_DIR", dir.to_str())]);
+
+        assert_eq!(
+            effective_value("content.javascript.enabled").unwrap(),
+            Some("false".into())
+        );
+        assert_eq!(
+            effective_value("content.blocking.whitelist")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(effective_value("content.autoplay").unwrap(), None);
+    }
+
+    #[test]
+    fn apply_settings_writes_once() {
+        let dir = testing::temp_dir("config-apply-settings");
+        let fifo = dir.join("fifo");
+        fs::write(&fifo, "").unwrap();
+        let _env = testing::set_env(&[("QUTE_FIFO", fifo.to_str())]);
+
+        apply_settings(&[("a", "1"), ("b", "two words"), ("c", "3")], true).unwrap();
+        assert!(!util::is_batching());
+        assert_eq!(
+            fs::read_to_string(&fifo).unwrap(),
+            "set -t a 1\nset -t b \"two words\"\nset -t c 3\n"
+        );
+    }
+
+    #[test]
+    fn apply_settings_joins_an_outer_batch() {
+        let (ret, commands) = testing::capture(|| {
+            apply_settings(&[("a", "1"), ("b", "2")], false)?;
+            assert!(util::is_batching());
+            Ok::<_, io::Error>(())
+        });
+        ret.unwrap();
+        assert_eq!(commands, ["set a 1", "set b 2"]);
+    }
+
+    #[test]
+    fn state_values_round_trip() {
+        for value in [
+            "plain",
+            "two\nlines",
+            r"back\slash",
+            "trailing\\",
+            r"\n literal",
+            "",
+        ] {
+            assert_eq!(unescape_state(&escape_state(value)), value);
+            assert!(!escape_state(value).contains('\n'));
+        }
+    }
+
+    #[test]
+    fn config_state_run_if_remembered_value() {
+        let dir = testing::temp_dir("config-state");
+        let _env = testing::set_env(&[("QUTE_DATA_DIR", dir.to_str())]);
+
+        let ((), commands) = testing::capture(|| {
+            let mut state = ConfigState::load().unwrap();
+            state
+                .set_and_remember("colors.webpage.darkmode.enabled", "true")
+                .unwrap();
+        });
+        assert_eq!(commands, ["set colors.webpage.darkmode.enabled true"]);
+
+        let state = ConfigState::load().unwrap();
+        let (ran, commands) = testing::capture(|| {
+            let ran = state.run_if("colors.webpage.darkmode.enabled", "true", "reload")?;
+            let skipped = state.run_if("colors.webpage.darkmode.enabled", "false", "back")?;
+            let unknown = state.run_if("content.autoplay", "true", "forward")?;
+            Ok::<_, io::Error>((ran, skipped, unknown))
+        });
+        assert_eq!(ran.unwrap(), (true, false, false));
+        assert_eq!(commands, ["reload"]);
+    }
+
+    #[test]
+    fn with_temp_setting_sets_and_restores() {
+        let (ret, commands) = testing::capture(|| {
+            with_temp_setting("content.javascript.enabled", "false", || {
+                util::send_command("reload").unwrap();
+                42
+            })
+        });
+        assert_eq!(ret.unwrap(), 42);
+        assert_eq!(
+            commands,
+            [
+                "set -t content.javascript.enabled false",
+                "reload",
+                "config-unset -t content.javascript.enabled",
+            ]
+        );
+    }
+
+    #[test]
+    fn bind_and_unbind_commands() {
+        let (ret, commands) = testing::capture(|| {
+            bind(",r", "reload ;; message-info\nreloaded", None)?;
+            unbind("<Ctrl-v>", Some(Mode::Caret))
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "bind ,r reload ;; message-info reloaded",
+                "unbind --mode caret <Ctrl-v>",
+            ]
+        );
+    }
+
+    #[test]
+    fn unset_with_and_without_temp() {
+        let (ret, commands) = testing::capture(|| {
+            unset("content.autoplay", false)?;
+            unset("content.autoplay", true)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "config-unset content.autoplay",
+                "config-unset -t content.autoplay",
+            ]
+        );
+    }
+}
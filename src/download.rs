@@ -0,0 +1,161 @@
+use crate::env;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Extensions given to files of downloads that are still in progress.
+const PARTIAL_EXTENSIONS: &[&str] = &["part", "crdownload", "download"];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Waits for the download of `filename` into [`download_dir`] to finish, returning the
+/// path of the downloaded file.
+///
+/// A download is considered finished once the file exists and no partial file for it
+/// (e.g. `{filename}.part`) remains. If this does not happen within `timeout`, an error
+/// of kind `TimedOut` is returned.
+///
+/// [`download_dir`]: ../env/fn.download_dir.html
+pub fn wait_for(filename: &str, timeout: Duration) -> Result<PathBuf, io::Error> {
+    let path = env::download_dir().join(filename);
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if path.is_file() && !has_partial(&path) {
+            return Ok(path);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("download of {} did not finish", filename),
+            ));
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn has_partial(path: &Path) -> bool {
+    PARTIAL_EXTENSIONS.iter().any(|ext| {
+        let mut partial = OsString::from(path.as_os_str());
+        partial.push(".");
+        partial.push(ext);
+        Path::new(&partial).exists()
+    })
+}
+
+/// Returns the partial files of downloads still in progress in [`download_dir`], e.g.
+/// `{filename}.part`.
+///
+/// qutebrowser can't be asked about its downloads (see [`Fifo`]), so this only sees
+/// downloads into [`download_dir`] whose partial files have one of the usual extensions.
+///
+/// [`Fifo`]: ../env/struct.Fifo.html
+/// [`download_dir`]: ../env/fn.download_dir.html
+pub fn in_progress() -> Result<Vec<PathBuf>, io::Error> {
+    let mut partials = Vec::new();
+    for entry in fs::read_dir(env::download_dir())? {
+        let path = entry?.path();
+        let is_partial = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| PARTIAL_EXTENSIONS.contains(&ext));
+        if is_partial && path.is_file() {
+            partials.push(path);
+        }
+    }
+    partials.sort();
+    Ok(partials)
+}
+
+/// Estimates the completion of the download with the partial file `path` as a fraction
+/// between 0 and 1, given its `expected_size` in bytes.
+///
+/// The expected size must come from elsewhere, e.g. the `Content-Length` of the
+/// resource, since the partial file doesn't record it. Returns `None` if `expected_size`
+/// is 0 or the size of `path` can't be read, e.g. because the download has finished and
+/// the partial file was renamed.
+pub fn progress(path: &Path, expected_size: u64) -> Option<f64> {
+    if expected_size == 0 {
+        return None;
+    }
+    let size = fs::metadata(path).ok()?.len();
+    Some((size as f64 / expected_size as f64).min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const TIMEOUT: Duration = Duration::from_millis(150);
+
+    #[test]
+    fn wait_for_finished_download() {
+        let dir = testing::temp_dir("download-wait-finished");
+        let _env = testing::set_env(&[("QUTE_DOWNLOAD_DIR", dir.to_str())]);
+        fs::write(dir.join("file.pdf"), "contents").unwrap();
+        assert_eq!(wait_for("file.pdf", TIMEOUT).unwrap(), dir.join("file.pdf"));
+    }
+
+    #[test]
+    fn wait_for_times_out_with_partial_file() {
+        let dir = testing::temp_dir("download-wait-partial");
+        let _env = testing::set_env(&[("QUTE_DOWNLOAD_DIR", dir.to_str())]);
+        fs::write(dir.join("file.pdf"), "").unwrap();
+        fs::write(dir.join("file.pdf.part"), "cont").unwrap();
+        let err = wait_for("file.pdf", TIMEOUT).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn wait_for_times_out_without_file() {
+        let dir = testing::temp_dir("download-wait-missing");
+        let _env = testing::set_env(&[("QUTE_DOWNLOAD_DIR", dir.to_str())]);
+        let err = wait_for("file.pdf", TIMEOUT).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn in_progress_lists_sorted_partial_files() {
+        let dir = testing::temp_dir("download-in-progress");
+        let _env = testing::set_env(&[("QUTE_DOWNLOAD_DIR", dir.to_str())]);
+        for name in &[
+            "video.mp4.crdownload",
+            "done.pdf",
+            "archive.zip.part",
+            "notes.download",
+            "part",
+            "image.png.partial",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        fs::create_dir(dir.join("folder.part")).unwrap();
+
+        assert_eq!(
+            in_progress().unwrap(),
+            [
+                dir.join("archive.zip.part"),
+                dir.join("notes.download"),
+                dir.join("video.mp4.crdownload"),
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_fraction() {
+        let dir = testing::temp_dir("download-progress");
+        let path = dir.join("file.pdf.part");
+        fs::write(&path, [0; 25]).unwrap();
+
+        assert_eq!(progress(&path, 100), Some(0.25));
+        assert_eq!(progress(&path, 0), None);
+        assert_eq!(progress(&path, 10), Some(1.0));
+        assert_eq!(progress(&dir.join("missing.part"), 100), None);
+    }
+}
@@ -1,7 +1,16 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::env;
-use std::fs::File;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// The method by which the userscript was launched, either `hints` (started via hints)
 /// or `command` (started via command or key binding).
@@ -11,22 +20,170 @@ pub enum SpawnMode {
     Hints(HintsVars),
     /// Indicates that the userscript was started via command or key binding.
     Command(CommandVars),
+    /// Indicates that the userscript was started in a way this crate doesn't know about,
+    /// with the given value of `QUTE_MODE`.
+    ///
+    /// No mode-specific variables are known to be set in this case.
+    Unknown(String),
 }
 
 const MODE: &str = "QUTE_MODE";
 
 /// Returns [`SpawnMode`] based on environment variable `QUTE_MODE`.
 ///
+/// Panics if `QUTE_MODE` is not set; see [`try_mode`] for a non-panicking version.
+///
 /// [`SpawnMode`]: ./enum.SpawnMode.html
+/// [`try_mode`]: ./fn.try_mode.html
 #[inline]
 pub fn mode() -> SpawnMode {
-    match unwrap_env(MODE).as_str() {
+    try_mode().unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Returns [`SpawnMode`] based on environment variable `QUTE_MODE`, or an error if it is
+/// not set or not valid unicode.
+///
+/// Values other than `hints` and `command` are returned as [`SpawnMode::Unknown`].
+///
+/// [`SpawnMode`]: ./enum.SpawnMode.html
+/// [`SpawnMode::Unknown`]: ./enum.SpawnMode.html#variant.Unknown
+pub fn try_mode() -> Result<SpawnMode, EnvError> {
+    let mode = try_var(MODE)?;
+    Ok(match mode.as_str() {
         "hints" => SpawnMode::Hints(HintsVars),
         "command" => SpawnMode::Command(CommandVars),
-        _ => panic!("invalid {} variable", MODE),
+        _ => SpawnMode::Unknown(mode),
+    })
+}
+
+impl SpawnMode {
+    /// Reads all variables specific to the mode at once, without panicking on missing
+    /// variables.
+    pub fn snapshot(&self) -> ModeSnapshot {
+        let var = |key| try_var(key).ok();
+        match self {
+            SpawnMode::Hints(_) => ModeSnapshot::Hints {
+                url: var(HINTS_URL),
+                selected_text: var(HINTS_SELECTED_TEXT),
+                selected_html: var(HINTS_SELECTED_HTML),
+            },
+            SpawnMode::Command(_) => ModeSnapshot::Command {
+                url: var(COMMAND_URL),
+                title: var(COMMAND_TITLE),
+                selected_text: var(COMMAND_SELECTED_TEXT),
+                count: var(COMMAND_COUNT),
+            },
+            SpawnMode::Unknown(_) => ModeSnapshot::Unknown,
+        }
     }
 }
 
+/// The known ways a userscript can be launched, without their variables; see
+/// [`SpawnMode`].
+///
+/// [`SpawnMode`]: ./enum.SpawnMode.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnModeKind {
+    Hints,
+    Command,
+}
+
+impl SpawnModeKind {
+    /// Returns the value of `QUTE_MODE` for the mode.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpawnModeKind::Hints => "hints",
+            SpawnModeKind::Command => "command",
+        }
+    }
+}
+
+/// Returns an error if the userscript was not launched in the `expected` mode, so that a
+/// script that only makes sense in one mode can bail out early.
+///
+/// The error converts into an `io::Error` of kind `InvalidInput`, so it can be shown to
+/// the user with [`report_on_error`], e.g.
+/// `report_on_error(require_mode(SpawnModeKind::Hints).map_err(Into::into))?`.
+///
+/// [`report_on_error`]: ../util/fn.report_on_error.html
+pub fn require_mode(expected: SpawnModeKind) -> Result<(), ModeError> {
+    let found = try_var(MODE).ok();
+    if found.as_deref() == Some(expected.as_str()) {
+        Ok(())
+    } else {
+        Err(ModeError { expected, found })
+    }
+}
+
+/// Error returned by [`require_mode`] when the userscript was launched in another mode.
+///
+/// [`require_mode`]: ./fn.require_mode.html
+#[derive(Clone, Debug)]
+pub struct ModeError {
+    /// The mode the userscript requires.
+    pub expected: SpawnModeKind,
+    /// The value of `QUTE_MODE`, or `None` if it is not set.
+    pub found: Option<String>,
+}
+
+impl fmt::Display for ModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(found) => write!(
+                f,
+                "userscript must be run in {} mode, not {} mode",
+                self.expected.as_str(),
+                found
+            ),
+            None => write!(
+                f,
+                "userscript must be run in {} mode from qutebrowser",
+                self.expected.as_str()
+            ),
+        }
+    }
+}
+
+impl Error for ModeError {}
+
+impl From<ModeError> for io::Error {
+    #[inline]
+    fn from(err: ModeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// The variables specific to a [`SpawnMode`], read by [`SpawnMode::snapshot`]. Variables
+/// that are not set (or not valid unicode) are `None`.
+///
+/// [`SpawnMode`]: ./enum.SpawnMode.html
+/// [`SpawnMode::snapshot`]: ./enum.SpawnMode.html#method.snapshot
+#[derive(Clone, Debug)]
+pub enum ModeSnapshot {
+    Hints {
+        url: Option<String>,
+        selected_text: Option<String>,
+        selected_html: Option<String>,
+    },
+    Command {
+        url: Option<String>,
+        title: Option<String>,
+        selected_text: Option<String>,
+        count: Option<String>,
+    },
+    Unknown,
+}
+
+/// Returns whether the userscript was spawned by qutebrowser, i.e. both `QUTE_FIFO` and
+/// `QUTE_MODE` are set.
+///
+/// This is `false` when the userscript is run directly from a shell, e.g. for testing.
+#[inline]
+pub fn is_qute_context() -> bool {
+    raw_os(FIFO).is_some() && raw_os(MODE).is_some()
+}
+
 /// Struct with methods for [`SpawnMode::Hints`]-specific variables.
 ///
 /// [`SpawnMode::Hints`]: ./enum.SpawnMode.html#variant.Hints
@@ -39,6 +196,10 @@ const HINTS_SELECTED_HTML: &str = "QUTE_SELECTED_HTML";
 
 impl HintsVars {
     /// Returns the URL selected via hints.
+    ///
+    /// This may be empty; see [`url_opt`].
+    ///
+    /// [`url_opt`]: ./fn.url_opt.html
     #[inline]
     pub fn url() -> String {
         unwrap_env(HINTS_URL)
@@ -70,6 +231,10 @@ const COMMAND_COUNT: &str = "QUTE_COUNT";
 
 impl CommandVars {
     /// Returns the URL of the current page.
+    ///
+    /// This may be empty, e.g. on some special pages; see [`url_opt`].
+    ///
+    /// [`url_opt`]: ./fn.url_opt.html
     #[inline]
     pub fn url() -> String {
         unwrap_env(COMMAND_URL)
@@ -81,6 +246,16 @@ impl CommandVars {
         unwrap_env(COMMAND_TITLE)
     }
 
+    /// Returns the title of the current page with HTML entities decoded, e.g. `&amp;` as
+    /// `&`, for use in filenames or messages.
+    ///
+    /// Numeric character references and the entities `&amp;`, `&lt;`, `&gt;`, `&quot;`,
+    /// `&apos;`, and `&nbsp;` are decoded; anything else is left as is.
+    #[inline]
+    pub fn title_decoded(&self) -> String {
+        crate::html::decode_entities(&self.title())
+    }
+
     /// Returns the text currently selected on the page.
     #[inline]
     pub fn selected_text(&self) -> String {
@@ -92,6 +267,175 @@ impl CommandVars {
     pub fn count(&self) -> String {
         unwrap_env(COMMAND_COUNT)
     }
+
+    /// Returns the `count` parsed as a number, or `default` if it is unset, empty, or not
+    /// a valid number.
+    #[inline]
+    pub fn count_or(&self, default: u32) -> u32 {
+        self.count_parsed().unwrap_or(default)
+    }
+
+    /// Returns the choice selected by the `count`, with a count of 1 selecting the first
+    /// of `choices`, or `default` if the count is unset, invalid, 0, or greater than the
+    /// number of choices.
+    ///
+    /// This is for userscripts bound to a key where the count picks an option, e.g.
+    /// `2<key>` for the second one.
+    pub fn count_choice<T: Clone>(&self, choices: &[T], default: T) -> T {
+        self.count_parsed()
+            .and_then(|count| count.checked_sub(1))
+            .and_then(|idx| choices.get(idx as usize))
+            .cloned()
+            .unwrap_or(default)
+    }
+
+    /// Returns the `count` parsed as a number, or `None` if it is unset, empty, or not a
+    /// valid number.
+    #[inline]
+    pub fn count_parsed(&self) -> Option<u32> {
+        env::var(COMMAND_COUNT)
+            .ok()
+            .and_then(|count| count.trim().parse().ok())
+    }
+}
+
+const URL: &str = "QUTE_URL";
+
+/// Returns the URL of the current page, or the URL selected via hints.
+///
+/// This may be empty, e.g. on some special pages; see [`url_opt`].
+///
+/// [`url_opt`]: ./fn.url_opt.html
+#[inline]
+pub fn current_url() -> String {
+    unwrap_env(URL)
+}
+
+/// Returns the URL of the current page, or the URL selected via hints, or `None` if
+/// `QUTE_URL` is not set or empty.
+///
+/// Unlike [`current_url`], [`HintsVars::url`], and [`CommandVars::url`], which return the
+/// variable as is, this treats an empty URL as absent.
+///
+/// [`current_url`]: ./fn.current_url.html
+/// [`HintsVars::url`]: ./struct.HintsVars.html#method.url
+/// [`CommandVars::url`]: ./struct.CommandVars.html#method.url
+#[inline]
+pub fn url_opt() -> Option<String> {
+    try_var(URL).ok().filter(|url| !url.is_empty())
+}
+
+const SELECTED_TEXT: &str = "QUTE_SELECTED_TEXT";
+
+/// Returns the text currently selected on the page, or the text of the element selected
+/// via hints, or `None` if `QUTE_SELECTED_TEXT` is not set.
+#[inline]
+pub fn selected_text() -> Option<String> {
+    env::var(SELECTED_TEXT).ok()
+}
+
+/// Returns whether any text is selected, i.e. `QUTE_SELECTED_TEXT` is set and not empty.
+#[inline]
+pub fn has_selection() -> bool {
+    selected_text().is_some_and(|text| !text.is_empty())
+}
+
+/// Returns a filesystem-safe key derived from [`current_url`], suitable for naming files
+/// that cache per-page data (e.g. with [`data_path`]).
+///
+/// The key is the 64-bit FNV-1a hash of the URL as 16 lowercase hex digits. It is stable
+/// across runs and platforms.
+///
+/// [`current_url`]: ./fn.current_url.html
+/// [`data_path`]: ./fn.data_path.html
+pub fn url_cache_key() -> String {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let hash = current_url().bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Replaces placeholders in a user-provided template with values from the environment,
+/// e.g. for userscripts that run a command configured by the user.
+///
+/// The recognized placeholders are:
+///
+/// - `{url}`: the URL of the current page, or the URL selected via hints (`QUTE_URL`)
+/// - `{title}`: the title of the current page (`QUTE_TITLE`)
+/// - `{selection}`: the selected text (`QUTE_SELECTED_TEXT`)
+/// - `{count}`: the count the userscript was run with (`QUTE_COUNT`)
+///
+/// Placeholders for variables that are not set (e.g. `{title}` when run via hints) are
+/// replaced with nothing. Anything else, including unrecognized placeholders, is left as
+/// is, and substituted values are not expanded again.
+pub fn expand_template(template: &str) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let placeholder = rest.find('}').and_then(|end| {
+            let key = match &rest[1..end] {
+                "url" => URL,
+                "title" => COMMAND_TITLE,
+                "selection" => SELECTED_TEXT,
+                "count" => COMMAND_COUNT,
+                _ => return None,
+            };
+            Some((key, end + 1))
+        });
+        match placeholder {
+            Some((key, len)) => {
+                expanded.push_str(&try_var(key).unwrap_or_default());
+                rest = &rest[len..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+const PRIVATE: &str = "QUTESCRIPT_PRIVATE";
+
+/// Returns whether the userscript should assume private browsing, and so avoid
+/// persisting data about the pages it is run on.
+///
+/// qutebrowser doesn't tell userscripts whether they were run from a private window, so
+/// this can only go by what the user has configured:
+///
+/// - If the variable `QUTESCRIPT_PRIVATE` is set, e.g. by starting qutebrowser with it
+///   for a private session, it decides: `1`, `true`, and `yes` (in any case) mean private
+///   browsing, and anything else means not.
+/// - Otherwise, this is `true` if `content.private_browsing` is enabled in the user's
+///   configuration files (see [`config::effective_value`]), in which case every window
+///   is private. Configuration files that can't be read are treated as not enabling it.
+///
+/// Private windows opened in an otherwise normal session can't be detected.
+///
+/// [`config::effective_value`]: ../config/fn.effective_value.html
+pub fn is_private() -> bool {
+    if let Ok(private) = try_var(PRIVATE) {
+        let private = private.trim();
+        return ["1", "true", "yes"]
+            .iter()
+            .any(|value| private.eq_ignore_ascii_case(value));
+    }
+
+    if raw_os(CONFIG_DIR).is_none() {
+        return false;
+    }
+    crate::config::effective_value("content.private_browsing")
+        .ok()
+        .flatten()
+        .is_some_and(|value| value == "true")
 }
 
 const USER_AGENT: &str = "QUTE_USER_AGENT";
@@ -102,12 +446,228 @@ pub fn user_agent() -> String {
     unwrap_env(USER_AGENT)
 }
 
+const VERSION: &str = "QUTE_VERSION";
+
+/// Returns the version of qutebrowser.
+#[inline]
+pub fn version() -> String {
+    unwrap_env(VERSION)
+}
+
+/// Returns the version of qutebrowser parsed as a [`Version`], or `None` if
+/// `QUTE_VERSION` is not set or doesn't start with a version number.
+///
+/// [`Version`]: ./struct.Version.html
+#[inline]
+pub fn version_parsed() -> Option<Version> {
+    try_var(VERSION)
+        .ok()
+        .and_then(|version| version.parse().ok())
+}
+
+/// A qutebrowser version number.
+///
+/// Versions are ordered by their numeric components, with a pre-release or development
+/// version ordered before the release with the same numbers. Build metadata, such as a
+/// `+{build}` suffix or the `-{commits}-g{hash}` suffix `git describe` adds for builds
+/// made after a release, orders a version after the release it is based on, with more
+/// commits ordered later.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The pre-release suffix after the version numbers, such as `dev` in `2.0.0-dev`.
+    pub pre: Option<String>,
+    /// Build metadata, such as `12-gabcdef` in `3.0.0-12-gabcdef` or `local` in
+    /// `3.0.0+local`.
+    pub build: Option<String>,
+}
+
+impl Version {
+    #[inline]
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: None,
+            build: None,
+        }
+    }
+}
+
+/// Returns the number of commits in a `git describe` suffix like `12-gabcdef`.
+fn git_commits(build: &str) -> Option<u32> {
+    let (commits, hash) = build.split_once("-g")?;
+    let hash = hash.strip_suffix("-dirty").unwrap_or(hash);
+    if commits.is_empty()
+        || !commits.bytes().all(|b| b.is_ascii_digit())
+        || hash.is_empty()
+        || !hash.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    commits.parse().ok()
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+            .then_with(|| match (&self.build, &other.build) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+                (Some(a), Some(b)) => git_commits(a).cmp(&git_commits(b)).then_with(|| a.cmp(b)),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        match &self.build {
+            Some(build) if git_commits(build).is_some() => write!(f, "-{}", build),
+            Some(build) => write!(f, "+{}", build),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error returned when a string doesn't start with a version number.
+#[derive(Clone, Debug)]
+pub struct ParseVersionError;
+
+impl fmt::Display for ParseVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid version")
+    }
+}
+
+impl Error for ParseVersionError {}
+
+impl FromStr for Version {
+    type Err = ParseVersionError;
+
+    /// Parses a version such as `1.14.0`, `v2.0.0-dev`, or `2.5` tolerantly: a leading
+    /// `v` is skipped and missing components are taken to be 0. After the numbers, a
+    /// `+{build}` suffix or a `git describe` suffix (`-{commits}-g{hash}`) becomes
+    /// [`build`], and anything else (minus a leading `-`) becomes [`pre`]. More than
+    /// three numeric components, as in `1.2.3.4`, are an error.
+    ///
+    /// [`build`]: #structfield.build
+    /// [`pre`]: #structfield.pre
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut rest = s.strip_prefix('v').unwrap_or(s);
+
+        let mut numbers = [0; 3];
+        for (i, number) in numbers.iter_mut().enumerate() {
+            if i > 0 {
+                match rest.strip_prefix('.') {
+                    Some(r) if r.starts_with(|c: char| c.is_ascii_digit()) => rest = r,
+                    _ => break,
+                }
+            }
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(ParseVersionError);
+            }
+            *number = rest[..end].parse().map_err(|_| ParseVersionError)?;
+            rest = &rest[end..];
+        }
+        if rest.starts_with('.') {
+            return Err(ParseVersionError);
+        }
+
+        let (pre, build) = match rest.split_once('+') {
+            Some((pre, build)) => (pre, Some(build)),
+            None => (rest, None),
+        };
+        let pre = pre.strip_prefix('-').unwrap_or(pre);
+        let (pre, build) = match build {
+            None if git_commits(pre).is_some() => ("", Some(pre)),
+            build => (pre, build),
+        };
+        let non_empty = |s: &str| if s.is_empty() { None } else { Some(s.into()) };
+        Ok(Version {
+            major: numbers[0],
+            minor: numbers[1],
+            patch: numbers[2],
+            pre: non_empty(pre),
+            build: build.and_then(non_empty),
+        })
+    }
+}
+
 const HTML: &str = "QUTE_HTML";
 
 /// Returns the path of a file containing the HTML source of the current page.
 #[inline]
 pub fn html() -> PathBuf {
-    unwrap_env(HTML).into()
+    unwrap_os(HTML).into()
+}
+
+/// Returns the path of a file containing the HTML source of the current page, after
+/// checking that the file exists and can be read.
+///
+/// An error is returned if `QUTE_HTML` is not set, or if it is set but the file can't be
+/// opened, e.g. because qutebrowser didn't generate it.
+#[inline]
+pub fn html_checked() -> Result<PathBuf, io::Error> {
+    checked_path(HTML)
+}
+
+/// Returns the HTML source of the current page, read from the file given by [`html`].
+///
+/// This fails if the file is not valid UTF-8; see [`html_contents_lossy`] for pages in
+/// other encodings.
+///
+/// [`html`]: ./fn.html.html
+/// [`html_contents_lossy`]: ./fn.html_contents_lossy.html
+#[inline]
+pub fn html_contents() -> Result<String, io::Error> {
+    fs::read_to_string(html())
+}
+
+/// Returns the HTML source of the current page, replacing any invalid UTF-8 sequences
+/// with `U+FFFD REPLACEMENT CHARACTER`.
+#[inline]
+pub fn html_contents_lossy() -> Result<String, io::Error> {
+    let bytes = fs::read(html())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Returns the HTML source of the current page (see [`html_contents_lossy`]) parsed into a
+/// document that can be queried with CSS selectors.
+///
+/// The whole file is read and parsed on each call, so call this once and reuse the
+/// document.
+///
+/// [`html_contents_lossy`]: ./fn.html_contents_lossy.html
+#[cfg(feature = "scraper")]
+#[inline]
+pub fn html_dom() -> Result<scraper::Html, io::Error> {
+    Ok(scraper::Html::parse_document(&html_contents_lossy()?))
 }
 
 const TEXT: &str = "QUTE_TEXT";
@@ -115,13 +675,51 @@ const TEXT: &str = "QUTE_TEXT";
 /// Returns the path of a file containing the plain text of the current page.
 #[inline]
 pub fn text() -> PathBuf {
-    unwrap_env(TEXT).into()
+    unwrap_os(TEXT).into()
+}
+
+/// Returns the path of a file containing the plain text of the current page, after
+/// checking that the file exists and can be read.
+///
+/// An error is returned if `QUTE_TEXT` is not set, or if it is set but the file can't be
+/// opened, e.g. because qutebrowser didn't generate it.
+#[inline]
+pub fn text_checked() -> Result<PathBuf, io::Error> {
+    checked_path(TEXT)
+}
+
+fn checked_path(key: &str) -> Result<PathBuf, io::Error> {
+    let path =
+        PathBuf::from(raw_os(key).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, EnvError::Missing(key.into()))
+        })?);
+    File::open(&path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("{} file {} can't be read: {}", key, path.display(), err),
+        )
+    })?;
+    Ok(path)
 }
 
 /// FIFO file to write commands to.
+///
+/// Besides [`write`], this implements `io::Write`, so commands can be written with
+/// `writeln!(fifo, "open {}", url)`. Written bytes are buffered until a newline, and each
+/// write that completes one or more commands sends them at once, so a command is never
+/// split across writes to the `FIFO`. Text after the last newline is only sent by
+/// `flush`, which terminates it with a newline; it is discarded if the `Fifo` is dropped
+/// without being flushed.
+///
+/// The `FIFO` is write-only: qutebrowser doesn't answer commands through it, so its state
+/// (e.g. settings, downloads, or the result of `jseval`) can only be found out from
+/// files it writes, such as sessions, `autoconfig.yml`, or downloads.
+///
+/// [`write`]: #method.write
 #[derive(Clone, Debug)]
 pub struct Fifo {
     path: PathBuf,
+    pending: Vec<u8>,
 }
 
 impl Fifo {
@@ -129,28 +727,238 @@ impl Fifo {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         Fifo {
             path: path.as_ref().into(),
+            pending: Vec::new(),
         }
     }
 
-    /// Open the `FIFO` as a file.
+    /// Open the `FIFO` as a file for appending.
     #[inline]
     pub fn file(&self) -> Result<File, io::Error> {
-        File::open(&self.path)
+        OpenOptions::new().append(true).open(&self.path)
     }
 
-    /// Write a string to the `FIFO` file.
+    /// Write a string to the `FIFO` file, terminated with a newline if it isn't already.
     ///
     /// On Unix/macOS, this is a named pipe and commands written to it will get executed
     /// immediately.
     /// On Windows, this is a regular file, and the commands in it will be executed as
-    /// soon as your userscript terminates.
+    /// soon as your userscript terminates. The file is opened for appending, so commands
+    /// written earlier by the same userscript are kept.
     #[inline]
     pub fn write(&self, message: &str) -> Result<(), io::Error> {
-        let mut file = self.file()?;
-        file.write_all(message.as_bytes())
+        let mut file = self.open_for_write()?;
+        file.write_all(terminated(message).as_bytes())
+    }
+
+    #[cfg(not(windows))]
+    #[inline]
+    fn open_for_write(&self) -> Result<File, io::Error> {
+        self.file()
+    }
+
+    /// Opens the `FIFO` for appending, or if that fails, for writing at its end.
+    ///
+    /// Writing from its start would overwrite commands qutebrowser hasn't executed yet, so
+    /// a warning is printed to stderr if the file isn't empty and can't be appended to.
+    #[cfg(windows)]
+    fn open_for_write(&self) -> Result<File, io::Error> {
+        use std::io::{Seek, SeekFrom};
+
+        match self.file() {
+            Ok(file) => Ok(file),
+            Err(append_err) => {
+                let mut file = OpenOptions::new().write(true).open(&self.path)?;
+                if file.metadata()?.len() > 0 {
+                    eprintln!(
+                        "qutescript: FIFO {} can't be opened for appending ({}); \
+                         writing at its end instead of overwriting unread commands",
+                        self.path.display(),
+                        append_err
+                    );
+                }
+                file.seek(SeekFrom::End(0))?;
+                Ok(file)
+            }
+        }
+    }
+
+    /// Write a string to the `FIFO` file, giving up with an error of kind `TimedOut` if
+    /// it can't be written within `dur`.
+    ///
+    /// On Unix/macOS, opening and writing to the named pipe blocks until qutebrowser reads
+    /// from it; this opens the pipe in non-blocking mode and retries until `dur` has
+    /// elapsed. On Windows, the `FIFO` is a regular file that never blocks, so this is the
+    /// same as [`write`].
+    ///
+    /// [`write`]: #method.write
+    #[cfg(unix)]
+    pub fn write_timeout(&self, message: &str, dur: Duration) -> Result<(), io::Error> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let deadline = Instant::now() + dur;
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "timed out writing to FIFO");
+
+        // Opening a pipe for writing without a reader fails with ENXIO in non-blocking
+        // mode, so retry until qutebrowser is reading.
+        let mut file = loop {
+            match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+            {
+                Ok(file) => break file,
+                Err(err) if err.raw_os_error() == Some(libc::ENXIO) => {}
+                Err(err) => return Err(err),
+            }
+            if Instant::now() >= deadline {
+                return Err(timed_out());
+            }
+            thread::sleep(FIFO_RETRY_INTERVAL);
+        };
+
+        let message = terminated(message);
+        let mut buf = message.as_bytes();
+        while !buf.is_empty() {
+            match file.write(buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => buf = &buf[n..],
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(timed_out());
+                    }
+                    thread::sleep(FIFO_RETRY_INTERVAL);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a string to the `FIFO` file.
+    ///
+    /// On Windows, the `FIFO` is a regular file that never blocks, so `dur` is ignored.
+    #[cfg(not(unix))]
+    #[inline]
+    pub fn write_timeout(&self, message: &str, _dur: Duration) -> Result<(), io::Error> {
+        self.write(message)
+    }
+
+    /// Returns a wrapper around the `FIFO` that waits at least `interval` between writes.
+    ///
+    /// qutebrowser may drop or reorder keys sent in quick succession with `fake-key`, so
+    /// scripts sending many commands in a row can use this to pace them.
+    #[inline]
+    pub fn with_rate_limit(self, interval: Duration) -> RateLimitedFifo {
+        RateLimitedFifo {
+            fifo: self,
+            interval,
+            last_write: None,
+        }
+    }
+}
+
+impl io::Write for Fifo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pending_len = self.pending.len();
+        self.pending.extend_from_slice(buf);
+
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let complete = self
+                .open_for_write()
+                .and_then(|mut file| file.write_all(&self.pending[..=end]));
+            if let Err(err) = complete {
+                self.pending.truncate(pending_len);
+                return Err(err);
+            }
+            self.pending.drain(..=end);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.pending.push(b'\n');
+        let written = self
+            .open_for_write()
+            .and_then(|mut file| file.write_all(&self.pending));
+        match written {
+            Ok(()) => self.pending.clear(),
+            Err(_) => {
+                self.pending.pop();
+            }
+        }
+        written
     }
 }
 
+/// A [`Fifo`] that waits a minimum interval between writes, returned by
+/// [`Fifo::with_rate_limit`].
+///
+/// [`Fifo`]: ./struct.Fifo.html
+/// [`Fifo::with_rate_limit`]: ./struct.Fifo.html#method.with_rate_limit
+#[derive(Clone, Debug)]
+pub struct RateLimitedFifo {
+    fifo: Fifo,
+    interval: Duration,
+    last_write: Option<Instant>,
+}
+
+impl RateLimitedFifo {
+    /// Write a string to the `FIFO` file like [`Fifo::write`], first sleeping until the
+    /// interval has passed since the previous write.
+    ///
+    /// On Windows, commands are only executed once the userscript terminates, so the
+    /// interval doesn't space out their execution there.
+    ///
+    /// [`Fifo::write`]: ./struct.Fifo.html#method.write
+    pub fn write(&mut self, message: &str) -> Result<(), io::Error> {
+        if let Some(last_write) = self.last_write {
+            let elapsed = last_write.elapsed();
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+
+        let ret = self.fifo.write(message);
+        self.last_write = Some(Instant::now());
+        ret
+    }
+
+    /// Returns the wrapped `FIFO`.
+    #[inline]
+    pub fn into_inner(self) -> Fifo {
+        self.fifo
+    }
+}
+
+#[cfg(unix)]
+const FIFO_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Terminates `message` with a newline if it isn't already, so that consecutive writes
+/// are read as separate commands.
+#[inline]
+fn terminated(message: &str) -> Cow<'_, str> {
+    if message.ends_with('\n') {
+        Cow::Borrowed(message)
+    } else {
+        Cow::Owned(format!("{}\n", message))
+    }
+}
+
+/// Returns whether the `FIFO` is a named pipe on the current platform.
+///
+/// On Unix/macOS, this returns `true`: commands written to the `FIFO` are executed
+/// immediately. On Windows, this returns `false`: the `FIFO` is a regular file and its
+/// commands are only executed once the userscript terminates.
+#[inline]
+pub fn fifo_is_pipe() -> bool {
+    !cfg!(windows)
+}
+
 const FIFO: &str = "QUTE_FIFO";
 
 /// Returns an instance of [`Fifo`] based on the environment variable `QUTE_FIFO`.
@@ -158,8 +966,16 @@ const FIFO: &str = "QUTE_FIFO";
 /// [`Fifo`]: ./struct.Fifo.html
 #[inline]
 pub fn fifo() -> Fifo {
-    let fifo_str = unwrap_env(FIFO);
-    Fifo::new(fifo_str)
+    Fifo::new(unwrap_os(FIFO))
+}
+
+/// Returns an instance of [`Fifo`] based on the environment variable `QUTE_FIFO`, or
+/// `None` if it is not set.
+///
+/// [`Fifo`]: ./struct.Fifo.html
+#[inline]
+pub fn try_fifo() -> Option<Fifo> {
+    raw_os(FIFO).map(Fifo::new)
 }
 
 const CONFIG_DIR: &str = "QUTE_CONFIG_DIR";
@@ -167,7 +983,28 @@ const CONFIG_DIR: &str = "QUTE_CONFIG_DIR";
 /// Returns the path of the directory containing qutebrowser's configuration.
 #[inline]
 pub fn config_dir() -> PathBuf {
-    unwrap_env(CONFIG_DIR).into()
+    unwrap_os(CONFIG_DIR).into()
+}
+
+const CONFIG_PY: &str = "config.py";
+
+/// Returns the path of the user's `config.py` in [`config_dir`], which may not exist.
+///
+/// [`config_dir`]: ./fn.config_dir.html
+#[inline]
+pub fn config_py_path() -> PathBuf {
+    config_dir().join(CONFIG_PY)
+}
+
+const AUTOCONFIG: &str = "autoconfig.yml";
+
+/// Returns the path of `autoconfig.yml` in [`config_dir`], where qutebrowser saves
+/// settings changed with `:set`. It may not exist.
+///
+/// [`config_dir`]: ./fn.config_dir.html
+#[inline]
+pub fn autoconfig_path() -> PathBuf {
+    config_dir().join(AUTOCONFIG)
 }
 
 const DATA_DIR: &str = "QUTE_DATA_DIR";
@@ -175,7 +1012,63 @@ const DATA_DIR: &str = "QUTE_DATA_DIR";
 /// Returns the path of the directory containing qutebrowser's data.
 #[inline]
 pub fn data_dir() -> PathBuf {
-    unwrap_env(DATA_DIR).into()
+    unwrap_os(DATA_DIR).into()
+}
+
+const STATE_DIR: &str = "qutescript";
+
+/// Returns the path of `name` in the directory under [`data_dir`] used to store state
+/// persisted across userscript invocations.
+///
+/// [`data_dir`]: ./fn.data_dir.html
+#[inline]
+pub fn data_path<P: AsRef<Path>>(name: P) -> PathBuf {
+    data_dir().join(STATE_DIR).join(name)
+}
+
+const USERSCRIPTS_DIR: &str = "userscripts";
+
+/// Returns the paths of the userscripts in the `userscripts` directories under
+/// [`config_dir`] and [`data_dir`].
+///
+/// Directories are skipped, as are files without any execute permission bits on Unix.
+/// Missing `userscripts` directories are treated as empty.
+///
+/// [`config_dir`]: ./fn.config_dir.html
+/// [`data_dir`]: ./fn.data_dir.html
+pub fn list_userscripts() -> Result<Vec<PathBuf>, io::Error> {
+    let mut scripts = Vec::new();
+    for dir in &[config_dir(), data_dir()] {
+        let entries = match fs::read_dir(dir.join(USERSCRIPTS_DIR)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && is_executable(&metadata) {
+                scripts.push(entry.path());
+            }
+        }
+    }
+
+    scripts.sort();
+    Ok(scripts)
+}
+
+#[cfg(unix)]
+#[inline]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
 }
 
 const DOWNLOAD_DIR: &str = "QUTE_DOWNLOAD_DIR";
@@ -183,18 +1076,654 @@ const DOWNLOAD_DIR: &str = "QUTE_DOWNLOAD_DIR";
 /// Returns the path of the downloads directory.
 #[inline]
 pub fn download_dir() -> PathBuf {
-    unwrap_env(DOWNLOAD_DIR).into()
+    unwrap_os(DOWNLOAD_DIR).into()
+}
+
+/// Returns the path of the downloads directory, falling back to the platform default
+/// downloads directory if `QUTE_DOWNLOAD_DIR` is not set.
+///
+/// If no platform default can be determined, the home directory is used, and failing
+/// that, the current directory.
+#[cfg(feature = "dirs")]
+#[inline]
+pub fn download_dir_or_default() -> PathBuf {
+    raw_os(DOWNLOAD_DIR)
+        .map(PathBuf::from)
+        .or_else(dirs::download_dir)
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The maximum length in bytes of the name returned by [`title_as_filename`], without the
+/// extension.
+///
+/// [`title_as_filename`]: ./fn.title_as_filename.html
+const MAX_FILENAME_STEM: usize = 200;
+
+/// Returns a filename derived from the title of the current page with the extension `ext`
+/// (e.g. `pdf`), for saving the page into [`download_dir`] or elsewhere.
+///
+/// Characters not allowed in filenames on common platforms (`/\:*?"<>|` and control
+/// characters) are replaced with `_`, whitespace is collapsed, and the name is truncated
+/// to 200 bytes. Names Windows reserves for devices (`CON`, `NUL`, `COM1`, and so on, in
+/// any case and with any extension) get a `_` after the device name, e.g. `NUL_.tar.pdf`
+/// for `NUL.tar`, so the name is safe to use on Unix and Windows. If the title is missing
+/// or empty, the host of the current URL is used instead, and failing that,
+/// `page-{timestamp}`.
+///
+/// [`download_dir`]: ./fn.download_dir.html
+pub fn title_as_filename(ext: &str) -> String {
+    let sanitize = |name: &str| {
+        let replaced: String = name
+            .chars()
+            .map(|c| {
+                if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let collapsed = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+        // Windows doesn't allow names ending in a dot, and leading dots hide files.
+        collapsed.trim_matches('.').trim().to_string()
+    };
+
+    let host = || {
+        let url = url_opt()?;
+        let rest = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+        let host = rest.split(['/', '?', '#']).next()?;
+        Some(host.rsplit('@').next()?.to_string())
+    };
+
+    let mut stem = try_var(COMMAND_TITLE)
+        .ok()
+        .map(|title| sanitize(&crate::html::decode_entities(&title)))
+        .filter(|stem| !stem.is_empty())
+        .or_else(|| host().map(|host| sanitize(&host)).filter(|s| !s.is_empty()))
+        .unwrap_or_else(|| {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            format!("page-{}", secs)
+        });
+
+    if stem.len() > MAX_FILENAME_STEM {
+        let mut end = MAX_FILENAME_STEM;
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        stem.truncate(end);
+        stem = stem.trim_end_matches(['.', ' ']).to_string();
+    }
+    if is_reserved_on_windows(&stem) {
+        let end = stem.find('.').unwrap_or(stem.len());
+        stem.insert(end, '_');
+    }
+
+    let ext = ext.trim_start_matches('.');
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+/// Returns whether Windows reserves `stem` for a device, which it does regardless of the
+/// extension and of trailing spaces before it.
+fn is_reserved_on_windows(stem: &str) -> bool {
+    let base = stem.split('.').next().unwrap_or(stem).trim_end();
+    let upper = base.to_ascii_uppercase();
+    match upper.as_bytes() {
+        b"CON" | b"PRN" | b"AUX" | b"NUL" => true,
+        [b'C', b'O', b'M', n] | [b'L', b'P', b'T', n] => (b'1'..=b'9').contains(n),
+        _ => false,
+    }
 }
 
 const COMMANDLINE_TEXT: &str = "QUTE_COMMANDLINE_TEXT";
 
 /// Returns the text in qutebrowser's command line.
+///
+/// qutebrowser doesn't export the position of the cursor in the command line, so a
+/// script that rewrites the text (e.g. with [`set_cmd_text`]) can't restore it; the
+/// cursor ends up at the end of the new text.
+///
+/// [`set_cmd_text`]: ../util/fn.set_cmd_text.html
 #[inline]
 pub fn commandline_text() -> String {
     unwrap_env(COMMANDLINE_TEXT)
 }
 
+/// Error returned when an environment variable can't be read.
+#[derive(Clone, Debug)]
+pub enum EnvError {
+    /// The variable is not set.
+    Missing(String),
+    /// The variable is set, but its value is not valid unicode.
+    NotUnicode(String, env::VarError),
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::Missing(key) => write!(f, "variable {} not set", key),
+            EnvError::NotUnicode(key, _) => write!(f, "variable {} is not valid unicode", key),
+        }
+    }
+}
+
+impl Error for EnvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EnvError::Missing(_) => None,
+            EnvError::NotUnicode(_, err) => Some(err),
+        }
+    }
+}
+
+/// Returns the value of the environment variable `key`, or an [`EnvError`] if it is not
+/// set or not valid unicode.
+///
+/// [`EnvError`]: ./enum.EnvError.html
+#[inline]
+pub fn try_var(key: &str) -> Result<String, EnvError> {
+    match raw_os(key) {
+        Some(value) => value
+            .into_string()
+            .map_err(|value| EnvError::NotUnicode(key.into(), env::VarError::NotUnicode(value))),
+        None => Err(EnvError::Missing(key.into())),
+    }
+}
+
+/// Returns an empty [`VarCache`] for memoizing variable lookups.
+///
+/// [`VarCache`]: ./struct.VarCache.html
+#[inline]
+pub fn cached() -> VarCache {
+    VarCache::default()
+}
+
+/// A cache of environment variables, returned by [`cached`], for scripts that read the
+/// same variables many times.
+///
+/// Each variable is read with [`try_var`] the first time it is requested, and that value
+/// (or error) is returned from then on, even if the variable changes; use
+/// [`invalidate`] to read it again. qutebrowser doesn't change the environment of a
+/// running userscript, so this only matters if the script changes it itself.
+///
+/// [`cached`]: ./fn.cached.html
+/// [`try_var`]: ./fn.try_var.html
+/// [`invalidate`]: #method.invalidate
+#[derive(Clone, Debug, Default)]
+pub struct VarCache {
+    values: HashMap<String, Result<String, EnvError>>,
+}
+
+impl VarCache {
+    /// Returns the value of the variable `key`, reading it on the first call.
+    pub fn get(&mut self, key: &str) -> Result<&str, EnvError> {
+        self.values
+            .entry(key.into())
+            .or_insert_with(|| try_var(key))
+            .as_deref()
+            .map_err(Clone::clone)
+    }
+
+    /// Forgets the cached value of `key`, so the next [`get`] reads it again.
+    ///
+    /// [`get`]: #method.get
+    #[inline]
+    pub fn invalidate(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    /// Forgets all cached values.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/// Returns the value of the environment variable `key` without checking that it is valid
+/// unicode, or `None` if it is not set.
+///
+/// Callers that only need to compare the value or use it as a path can work with the
+/// `OsString` directly, skipping the UTF-8 validation done by [`try_var`], which also
+/// means that non-unicode paths are supported. Accessors returning paths are built on
+/// this.
+///
+/// [`try_var`]: ./fn.try_var.html
+#[inline]
+pub fn raw_os(key: &str) -> Option<OsString> {
+    env::var_os(key)
+}
+
+#[inline]
+fn unwrap_os(key: &str) -> OsString {
+    raw_os(key).unwrap_or_else(|| panic!("{}", EnvError::Missing(key.into())))
+}
+
 #[inline]
 fn unwrap_env(key: &str) -> String {
-    env::var(key).expect(&format!("variable {} not set", key))
+    try_var(key).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn title_as_filename_replaces_invalid_characters() {
+        let _env = testing::set_env(&[
+            ("QUTE_TITLE", Some("a/b\\c: d? <e> 🦀")),
+            ("QUTE_URL", Some("https://example.com/")),
+        ]);
+        assert_eq!(title_as_filename("pdf"), "a_b_c_ d_ _e_ 🦀.pdf");
+    }
+
+    #[test]
+    fn title_as_filename_avoids_reserved_windows_names() {
+        for (title, expected) in [
+            ("CON", "CON_.pdf"),
+            ("nul", "nul_.pdf"),
+            ("com1", "com1_.pdf"),
+            ("Lpt9.txt", "Lpt9_.txt.pdf"),
+            ("nul.tar", "nul_.tar.pdf"),
+            ("CONSOLE", "CONSOLE.pdf"),
+            ("COM0", "COM0.pdf"),
+        ] {
+            let _env = testing::set_env(&[("QUTE_TITLE", Some(title))]);
+            assert_eq!(title_as_filename("pdf"), expected, "{}", title);
+        }
+    }
+
+    #[test]
+    fn title_as_filename_falls_back_to_host() {
+        let _env = testing::set_env(&[
+            ("QUTE_TITLE", Some(" ... ")),
+            ("QUTE_URL", Some("https://user@example.com:8080/path")),
+        ]);
+        assert_eq!(title_as_filename(".html"), "example.com_8080.html");
+    }
+
+    #[test]
+    fn fifo_write_sends_complete_lines() {
+        let dir = testing::temp_dir("env-fifo-write");
+        let path = dir.join("fifo");
+        fs::write(&path, "").unwrap();
+        let mut fifo = Fifo::new(&path);
+
+        writeln!(fifo, "message-info {}", 1).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "message-info 1\n");
+
+        // A partial line is held back until it is completed or flushed.
+        write!(fifo, "message-info").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "message-info 1\n");
+        write!(fifo, " 2\nmessage-").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "message-info 1\nmessage-info 2\n"
+        );
+        write!(fifo, "info 3").unwrap();
+        fifo.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "message-info 1\nmessage-info 2\nmessage-info 3\n"
+        );
+
+        // Nothing is pending after a flush.
+        fifo.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "message-info 1\nmessage-info 2\nmessage-info 3\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_timeout_times_out_without_reader() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = testing::temp_dir("env-write-timeout");
+        let path = dir.join("fifo");
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        let start = Instant::now();
+        let err = Fifo::new(&path)
+            .write_timeout("message-info unread", Duration::from_millis(50))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn expand_template_substitutes_variables() {
+        let _env = testing::set_env(&[
+            ("QUTE_URL", Some("https://example.com/")),
+            ("QUTE_SELECTED_TEXT", Some("{url} text")),
+            ("QUTE_TITLE", None),
+        ]);
+        assert_eq!(
+            expand_template("mpv {url} --title={title} # {selection} {other} {"),
+            "mpv https://example.com/ --title= # {url} text {other} {"
+        );
+    }
+
+    #[test]
+    fn count_or_parses_count() {
+        for (count, expected) in [
+            (Some("5"), 5),
+            (Some(" 7 "), 7),
+            (Some(""), 1),
+            (Some("five"), 1),
+            (Some("-2"), 1),
+            (None, 1),
+        ] {
+            let _env = testing::set_env(&[("QUTE_COUNT", count)]);
+            assert_eq!(CommandVars.count_or(1), expected, "{:?}", count);
+        }
+    }
+
+    #[test]
+    fn count_choice_selects_by_count() {
+        let choices = ["a", "b", "c"];
+        for (count, expected) in [
+            (Some("1"), "a"),
+            (Some("3"), "c"),
+            (Some("0"), "default"),
+            (Some("4"), "default"),
+            (Some(""), "default"),
+            (None, "default"),
+        ] {
+            let _env = testing::set_env(&[("QUTE_COUNT", count)]);
+            assert_eq!(
+                CommandVars.count_choice(&choices, "default"),
+                expected,
+                "{:?}",
+                count
+            );
+        }
+
+        let _env = testing::set_env(&[("QUTE_COUNT", Some("1"))]);
+        assert_eq!(CommandVars.count_choice(&[], "default"), "default");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn try_var_reports_non_unicode_values() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let _lock = testing::lock_env();
+        let key = "QUTESCRIPT_TEST_NOT_UNICODE";
+        env::set_var(key, OsStr::from_bytes(b"caf\xe9"));
+        let ret = try_var(key);
+        let raw = raw_os(key);
+        env::remove_var(key);
+
+        match ret {
+            Err(EnvError::NotUnicode(name, _)) => assert_eq!(name, key),
+            other => panic!("expected NotUnicode, got {:?}", other),
+        }
+        assert_eq!(raw.unwrap().as_bytes(), b"caf\xe9");
+        assert!(matches!(try_var(key), Err(EnvError::Missing(_))));
+    }
+
+    #[test]
+    fn is_qute_context_requires_fifo_and_mode() {
+        for (fifo, mode, expected) in [
+            (Some("/tmp/fifo"), Some("command"), true),
+            (Some("/tmp/fifo"), None, false),
+            (None, Some("hints"), false),
+            (None, None, false),
+        ] {
+            let _env = testing::set_env(&[("QUTE_FIFO", fifo), ("QUTE_MODE", mode)]);
+            assert_eq!(is_qute_context(), expected, "{:?} {:?}", fifo, mode);
+        }
+    }
+
+    #[test]
+    fn has_selection_requires_non_empty_text() {
+        for (selected, expected) in [(Some("text"), true), (Some(""), false), (None, false)] {
+            let _env = testing::set_env(&[("QUTE_SELECTED_TEXT", selected)]);
+            assert_eq!(has_selection(), expected, "{:?}", selected);
+        }
+    }
+
+    #[test]
+    fn url_opt_skips_empty_urls() {
+        for (url, expected) in [
+            (Some("https://example.com/"), Some("https://example.com/")),
+            (Some(""), None),
+            (None, None),
+        ] {
+            let _env = testing::set_env(&[("QUTE_URL", url)]);
+            assert_eq!(url_opt().as_deref(), expected, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn snapshot_reads_set_variables() {
+        let _env = testing::set_env(&[
+            ("QUTE_MODE", Some("hints")),
+            ("QUTE_URL", Some("https://example.com/")),
+            ("QUTE_SELECTED_TEXT", None),
+            ("QUTE_SELECTED_HTML", Some("<b>link</b>")),
+            ("QUTE_TITLE", None),
+            ("QUTE_COUNT", Some("3")),
+        ]);
+        match try_mode().unwrap().snapshot() {
+            ModeSnapshot::Hints {
+                url,
+                selected_text,
+                selected_html,
+            } => {
+                assert_eq!(url.as_deref(), Some("https://example.com/"));
+                assert_eq!(selected_text, None);
+                assert_eq!(selected_html.as_deref(), Some("<b>link</b>"));
+            }
+            other => panic!("expected a hints snapshot, got {:?}", other),
+        }
+
+        env::set_var("QUTE_MODE", "command");
+        match try_mode().unwrap().snapshot() {
+            ModeSnapshot::Command {
+                url,
+                title,
+                selected_text,
+                count,
+            } => {
+                assert_eq!(url.as_deref(), Some("https://example.com/"));
+                assert_eq!(title, None);
+                assert_eq!(selected_text, None);
+                assert_eq!(count.as_deref(), Some("3"));
+            }
+            other => panic!("expected a command snapshot, got {:?}", other),
+        }
+
+        env::set_var("QUTE_MODE", "other");
+        assert!(matches!(
+            try_mode().unwrap().snapshot(),
+            ModeSnapshot::Unknown
+        ));
+    }
+
+    #[test]
+    fn require_mode_checks_qute_mode() {
+        let _env = testing::set_env(&[("QUTE_MODE", Some("hints"))]);
+        assert!(require_mode(SpawnModeKind::Hints).is_ok());
+
+        let err = require_mode(SpawnModeKind::Command).unwrap_err();
+        assert_eq!(err.expected, SpawnModeKind::Command);
+        assert_eq!(err.found.as_deref(), Some("hints"));
+        assert_eq!(
+            err.to_string(),
+            "userscript must be run in command mode, not hints mode"
+        );
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidInput);
+
+        env::remove_var("QUTE_MODE");
+        assert_eq!(require_mode(SpawnModeKind::Hints).unwrap_err().found, None);
+    }
+
+    #[test]
+    fn is_private_override_and_config() {
+        let dir = testing::temp_dir("env-is-private");
+        fs::write(
+            dir.join("config.py"),
+            "config.load_autoconfig(False)\nc.content.private_browsing = True\n",
+        )
+        .unwrap();
+
+        for (private, config_dir, expected) in [
+            (Some("1"), None, true),
+            (Some("Yes"), None, true),
+            (Some("0"), dir.to_str(), false),
+            (None, dir.to_str(), true),
+            (None, None, false),
+        ] {
+            let _env = testing::set_env(&[
+                ("QUTESCRIPT_PRIVATE", private),
+                ("QUTE_CONFIG_DIR", config_dir),
+            ]);
+            assert_eq!(is_private(), expected, "{:?} {:?}", private, config_dir);
+        }
+
+        fs::write(
+            dir.join("config.py"),
+            "c.content.private_browsing = False\n",
+        )
+        .unwrap();
+        let _env = testing::set_env(&[
+            ("QUTESCRIPT_PRIVATE", None),
+            ("QUTE_CONFIG_DIR", dir.to_str()),
+        ]);
+        assert!(!is_private());
+    }
+
+    #[test]
+    fn url_cache_key_is_fnv1a() {
+        for (url, key) in [
+            ("", "cbf29ce484222325"),
+            ("a", "af63dc4c8601ec8c"),
+            ("foobar", "85944171f73967e8"),
+        ] {
+            let _env = testing::set_env(&[("QUTE_URL", Some(url))]);
+            assert_eq!(url_cache_key(), key, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn list_userscripts_skips_directories_and_non_executables() {
+        let config_dir = testing::temp_dir("env-userscripts-config");
+        let data_dir = testing::temp_dir("env-userscripts-data");
+        let scripts = config_dir.join("userscripts");
+        fs::create_dir_all(scripts.join("lib")).unwrap();
+        fs::write(scripts.join("run"), "#!/bin/sh\n").unwrap();
+        fs::write(scripts.join("README"), "docs\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = fs::metadata(scripts.join("run")).unwrap().permissions();
+            permissions.set_mode(0o755);
+            fs::set_permissions(scripts.join("run"), permissions).unwrap();
+        }
+
+        let _env = testing::set_env(&[
+            ("QUTE_CONFIG_DIR", config_dir.to_str()),
+            ("QUTE_DATA_DIR", data_dir.to_str()),
+        ]);
+        let found = list_userscripts().unwrap();
+        if cfg!(unix) {
+            assert_eq!(found, [scripts.join("run")]);
+        } else {
+            assert_eq!(found, [scripts.join("README"), scripts.join("run")]);
+        }
+    }
+
+    #[test]
+    fn html_contents_lossy_replaces_invalid_utf8() {
+        let dir = testing::temp_dir("env-html-lossy");
+        let path = dir.join("page.html");
+        fs::write(&path, b"<p>caf\xe9</p>").unwrap();
+        let _env = testing::set_env(&[("QUTE_HTML", path.to_str())]);
+
+        assert_eq!(html_contents_lossy().unwrap(), "<p>caf\u{fffd}</p>");
+        assert_eq!(
+            html_contents().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn fifo_is_pipe_matches_target() {
+        assert_eq!(fifo_is_pipe(), cfg!(unix));
+    }
+
+    #[test]
+    fn reserved_windows_names_ignore_extensions() {
+        for stem in ["NUL", "nul.tar.gz", "Lpt9.txt", "CON .txt", "com3"] {
+            assert!(is_reserved_on_windows(stem), "{}", stem);
+        }
+        for stem in [
+            "NUL_",
+            "nul_.tar.gz",
+            "Lpt9_.txt",
+            "CONSOLE",
+            "COM10",
+            "LPT0",
+        ] {
+            assert!(!is_reserved_on_windows(stem), "{}", stem);
+        }
+    }
+
+    #[test]
+    fn version_from_str() {
+        let parse = |s: &str| s.parse::<Version>().unwrap();
+        assert_eq!(parse("1.14.0"), Version::new(1, 14, 0));
+        assert_eq!(parse(" v2.5 "), Version::new(2, 5, 0));
+        assert_eq!(parse("3"), Version::new(3, 0, 0));
+
+        let dev = parse("2.0.0-dev");
+        assert_eq!(dev.pre.as_deref(), Some("dev"));
+        assert_eq!(dev.build, None);
+
+        let described = parse("3.0.0-12-gabcdef");
+        assert_eq!(described.pre, None);
+        assert_eq!(described.build.as_deref(), Some("12-gabcdef"));
+        assert_eq!(described.to_string(), "3.0.0-12-gabcdef");
+
+        let both = parse("3.1.0-rc1+local");
+        assert_eq!(both.pre.as_deref(), Some("rc1"));
+        assert_eq!(both.build.as_deref(), Some("local"));
+        assert_eq!(both.to_string(), "3.1.0-rc1+local");
+
+        for invalid in ["", "v", "x1.0", "1.2.3.4", "1.2."] {
+            assert!(invalid.parse::<Version>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn version_ordering() {
+        let parse = |s: &str| s.parse::<Version>().unwrap();
+        let ordered = [
+            "2.5.4",
+            "3.0.0-dev",
+            "3.0.0-rc1",
+            "3.0.0",
+            "3.0.0+local",
+            "3.0.0-9-g0123abc",
+            "3.0.0-12-gabcdef",
+            "3.0.1",
+            "3.1",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(parse(pair[0]) < parse(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(parse("v3.0").cmp(&parse("3.0.0")), Ordering::Equal);
+    }
 }
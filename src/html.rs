@@ -0,0 +1,295 @@
+//! A minimal HTML scanner for extracting links from page sources.
+//!
+//! It doesn't build a DOM; it only recognizes tags, attributes, comments, and the raw text
+//! of `script` and `style` elements well enough to find `<a href>` and `<link>` elements
+//! in real-world pages.
+
+/// A tag found by [`Tags`], given by its byte range in the source.
+struct Tag<'a> {
+    /// The lowercase name of the tag.
+    name: &'a str,
+    closing: bool,
+    start: usize,
+    end: usize,
+}
+
+/// An iterator over the tags of an HTML source, skipping comments and the contents of
+/// `script` and `style` elements.
+struct Tags<'a> {
+    html: &'a str,
+    lower: String,
+    pos: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn new(html: &'a str) -> Self {
+        Tags {
+            html,
+            lower: html.to_ascii_lowercase(),
+            pos: 0,
+        }
+    }
+
+    fn next_tag(&mut self) -> Option<Tag<'_>> {
+        loop {
+            let start = self.pos + self.lower[self.pos..].find('<')?;
+            if self.lower[start..].starts_with("<!--") {
+                self.pos = self.lower[start..]
+                    .find("-->")
+                    .map_or(self.html.len(), |end| start + end + 3);
+                continue;
+            }
+
+            let end = tag_end(self.html, start);
+            let (name, closing) = tag_name(&self.lower[start..end]);
+            self.pos = end;
+            if !closing && (name == "script" || name == "style") {
+                let close = format!("</{}", name);
+                self.pos = self.lower[end..]
+                    .find(&close)
+                    .map_or(self.html.len(), |idx| end + idx);
+            }
+            return Some(Tag {
+                name,
+                closing,
+                start,
+                end,
+            });
+        }
+    }
+}
+
+/// Returns the `href` attribute and text of every `a` element with an `href`, in
+/// document order.
+///
+/// Entities in both are decoded, and whitespace in the text is collapsed.
+pub fn links(html: &str) -> Vec<(String, String)> {
+    let mut tags = Tags::new(html);
+    let mut links = Vec::new();
+    let mut open: Option<(String, usize)> = None;
+
+    while let Some(tag) = tags.next_tag() {
+        if tag.name != "a" {
+            continue;
+        }
+        if let Some((href, text_start)) = open.take() {
+            links.push((href, text(&html[text_start..tag.start])));
+        }
+        if !tag.closing {
+            open = attr(&html[tag.start..tag.end], "href").map(|href| (href, tag.end));
+        }
+    }
+
+    if let Some((href, text_start)) = open {
+        links.push((href, text(&html[text_start..])));
+    }
+    links
+}
+
+/// Returns the `href` attribute of the first `link` element with `canonical` among its
+/// `rel` values.
+pub fn canonical(html: &str) -> Option<String> {
+    let mut tags = Tags::new(html);
+    while let Some(tag) = tags.next_tag() {
+        if tag.name != "link" || tag.closing {
+            continue;
+        }
+        let source = &html[tag.start..tag.end];
+        let is_canonical = attr(source, "rel").is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|value| value.eq_ignore_ascii_case("canonical"))
+        });
+        if is_canonical {
+            if let Some(href) = attr(source, "href").filter(|href| !href.is_empty()) {
+                return Some(href);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the index just past the `>` ending the tag starting at `start`, ignoring any
+/// `>` in quoted attribute values.
+fn tag_end(html: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (idx, c) in html[start + 1..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return start + 1 + idx + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Returns the lowercase name of `tag` and whether it is a closing tag.
+fn tag_name(tag: &str) -> (&str, bool) {
+    let tag = &tag[1..];
+    let (tag, closing) = match tag.strip_prefix('/') {
+        Some(rest) => (rest, true),
+        None => (tag, false),
+    };
+    let len = tag
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(tag.len());
+    (&tag[..len], closing)
+}
+
+/// Returns the decoded value of the attribute `name` of `tag`, if present.
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let (tag_name_, _) = tag_name(tag);
+    let mut rest = tag[1 + tag_name_.len()..].trim_end_matches('>');
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+
+        let key_len = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_len];
+        rest = rest[key_len..].trim_start();
+
+        let mut value = None;
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(q @ '"') | Some(q @ '\'') => {
+                    let inner = &after[1..];
+                    let len = inner.find(q).unwrap_or(inner.len());
+                    (&inner[..len], inner.get(len + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let len = after.find(char::is_whitespace).unwrap_or(after.len());
+                    after.split_at(len)
+                }
+            };
+            value = Some(raw);
+            rest = remaining;
+        }
+
+        if key.eq_ignore_ascii_case(name) {
+            return Some(decode_entities(value.unwrap_or("")).trim().into());
+        }
+    }
+}
+
+/// Returns the text of an HTML fragment with tags removed, entities decoded, and
+/// whitespace collapsed.
+fn text(fragment: &str) -> String {
+    let mut stripped = String::with_capacity(fragment.len());
+    let mut pos = 0;
+    while let Some(offset) = fragment[pos..].find('<') {
+        let start = pos + offset;
+        stripped.push_str(&fragment[pos..start]);
+        stripped.push(' ');
+        pos = tag_end(fragment, start);
+    }
+    stripped.push_str(&fragment[pos..]);
+
+    decode_entities(&stripped)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decodes numeric character references and the most common named entities, leaving
+/// anything else as is.
+pub fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&len| len > 0 && len <= 10)
+            .map(|len| &rest[1..=len]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match entity.strip_prefix('#')? {
+                    hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16),
+                    dec => dec.parse(),
+                };
+                code.ok().and_then(char::from_u32)
+            }
+        });
+
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_in_document_order() {
+        let html = r#"<html><head><style>a { color: red; }</style></head><body>
+            <a href="https://example.com/a">First   <b>link</b></a>
+            <!-- <a href="https://example.com/comment">hidden</a> -->
+            <script>document.write('<a href="https://example.com/script">x</a>');</script>
+            <A HREF='/relative?a=1&amp;b=2'>Second &amp; last</A>
+            <a name="anchor">no href</a>
+        </body></html>"#;
+        assert_eq!(
+            links(html),
+            [
+                (
+                    "https://example.com/a".to_string(),
+                    "First link".to_string()
+                ),
+                ("/relative?a=1&b=2".to_string(), "Second & last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_link() {
+        let html = r#"<head>
+            <link rel="stylesheet" href="/style.css">
+            <link rel="alternate canonical" href="">
+            <LINK REL="Canonical" HREF="https://example.com/page?a=1&amp;b=2">
+        </head>"#;
+        assert_eq!(
+            canonical(html).as_deref(),
+            Some("https://example.com/page?a=1&b=2")
+        );
+        assert_eq!(
+            canonical(r#"<link rel="icon" href="/favicon.ico"><a href="/x">x</a>"#),
+            None
+        );
+    }
+
+    #[test]
+    fn decode_entities_named_and_numeric() {
+        assert_eq!(decode_entities("a &amp; b &lt;c&gt;"), "a & b <c>");
+        assert_eq!(decode_entities("&quot;&apos;&nbsp;"), "\"'\u{a0}");
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;"), "ABC");
+        assert_eq!(
+            decode_entities("&unknown; & &#xffffffff; &amp"),
+            "&unknown; & &#xffffffff; &amp"
+        );
+    }
+}
@@ -1,5 +1,16 @@
+pub mod config;
+pub mod download;
 pub mod env;
+pub mod message;
+pub mod picker;
+pub mod session;
 pub mod util;
 
+mod html;
+mod state;
+#[cfg(test)]
+mod testing;
+mod yaml;
+
 pub use env::*;
 pub use util::*;
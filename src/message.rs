@@ -0,0 +1,232 @@
+use crate::env;
+use crate::state;
+use crate::util::{self, escape_arg, MsgLevel};
+
+use std::fmt;
+use std::io::{self, Write};
+
+const LAST_STATUS: &str = "last-status";
+
+/// The maximum number of messages [`echo_to_user`] shows.
+///
+/// [`echo_to_user`]: ./fn.echo_to_user.html
+pub const MAX_ECHO_LINES: usize = 10;
+
+/// Shows a message of the given level, falling back to printing it if the `FIFO` isn't
+/// available.
+///
+/// If `QUTE_FIFO` is set, the message is sent as a `message-{level}` command. Otherwise,
+/// info messages are printed to stdout, and warnings and errors to stderr prefixed with
+/// `Warning: ` or `Error: `. When the userscript is run with `spawn --output-messages`,
+/// qutebrowser shows stdout as info messages and stderr as error messages, so the message
+/// is surfaced either way; when run from a shell, it is printed to the terminal.
+pub fn show(level: MsgLevel, text: &str) -> Result<(), io::Error> {
+    if env::try_fifo().is_some() {
+        return util::message(level, text);
+    }
+
+    match level {
+        MsgLevel::Info => writeln!(io::stdout(), "{}", text),
+        MsgLevel::Warning => writeln!(io::stderr(), "Warning: {}", text),
+        MsgLevel::Error => writeln!(io::stderr(), "Error: {}", text),
+    }
+}
+
+/// Shows an info message; see [`show`].
+///
+/// [`show`]: ./fn.show.html
+#[inline]
+pub fn info(text: &str) -> Result<(), io::Error> {
+    show(MsgLevel::Info, text)
+}
+
+/// Shows a warning message; see [`show`].
+///
+/// [`show`]: ./fn.show.html
+#[inline]
+pub fn warning(text: &str) -> Result<(), io::Error> {
+    show(MsgLevel::Warning, text)
+}
+
+/// Shows an error message; see [`show`].
+///
+/// [`show`]: ./fn.show.html
+#[inline]
+pub fn error(text: &str) -> Result<(), io::Error> {
+    show(MsgLevel::Error, text)
+}
+
+/// Shows `text` as an info message (see [`info`]), unless it is the same as the last
+/// message shown by this function.
+///
+/// The last message is stored in a state file under [`data_dir`], so repeated calls
+/// across invocations of a polling userscript don't show the same message again.
+///
+/// [`info`]: ./fn.info.html
+/// [`data_dir`]: ../env/fn.data_dir.html
+pub fn status(text: &str) -> Result<(), io::Error> {
+    if state::read(LAST_STATUS)?.as_deref() == Some(text) {
+        return Ok(());
+    }
+
+    info(text)?;
+    state::write(LAST_STATUS, text)
+}
+
+/// Shows each of `lines` as an info message (see [`info`]), e.g. to report multi-line
+/// results or debugging output.
+///
+/// qutebrowser stacks messages in the status bar, so to avoid flooding it, at most
+/// [`MAX_ECHO_LINES`] messages are shown: if there are more lines, the first
+/// `MAX_ECHO_LINES - 1` are shown, followed by a message with the number of lines left
+/// out.
+///
+/// [`info`]: ./fn.info.html
+/// [`MAX_ECHO_LINES`]: ./constant.MAX_ECHO_LINES.html
+pub fn echo_to_user(lines: &[&str]) -> Result<(), io::Error> {
+    if lines.len() <= MAX_ECHO_LINES {
+        return lines.iter().try_for_each(|line| info(line));
+    }
+
+    let (shown, omitted) = lines.split_at(MAX_ECHO_LINES - 1);
+    shown.iter().try_for_each(|line| info(line))?;
+    info(&format!("... and {} more lines", omitted.len()))
+}
+
+/// Builder for a single message made of `key=value` fields, e.g. for reporting progress
+/// as `processed=5 skipped=2 errors=0`.
+#[derive(Clone, Debug, Default)]
+pub struct MessageBuilder {
+    text: String,
+}
+
+impl MessageBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the field `key={value}`.
+    ///
+    /// Values containing whitespace or quotes are quoted, so fields can be told apart.
+    pub fn field<V: fmt::Display>(mut self, key: &str, value: V) -> Self {
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(key);
+        self.text.push('=');
+        self.text.push_str(&escape_arg(&value.to_string()));
+        self
+    }
+
+    /// Returns the message text.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Shows the message with the given level; see [`show`].
+    ///
+    /// [`show`]: ./fn.show.html
+    #[inline]
+    pub fn send(&self, level: MsgLevel) -> Result<(), io::Error> {
+        show(level, &self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn echo_to_user_caps_messages() {
+        let _env = testing::set_env(&[("QUTE_FIFO", Some("/nonexistent/fifo"))]);
+
+        let lines: Vec<String> = (1..=MAX_ECHO_LINES).map(|n| n.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let (ret, commands) = testing::capture(|| echo_to_user(&lines));
+        ret.unwrap();
+        assert_eq!(commands.len(), MAX_ECHO_LINES);
+        assert_eq!(
+            commands[MAX_ECHO_LINES - 1],
+            format!("message-info {}", MAX_ECHO_LINES)
+        );
+
+        let lines: Vec<String> = (1..=MAX_ECHO_LINES + 5).map(|n| n.to_string()).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let (ret, commands) = testing::capture(|| echo_to_user(&lines));
+        ret.unwrap();
+        assert_eq!(commands.len(), MAX_ECHO_LINES);
+        assert_eq!(commands[0], "message-info 1");
+        assert_eq!(
+            commands[MAX_ECHO_LINES - 2],
+            format!("message-info {}", MAX_ECHO_LINES - 1)
+        );
+        assert_eq!(
+            commands[MAX_ECHO_LINES - 1],
+            "message-info ... and 6 more lines"
+        );
+    }
+
+    #[test]
+    fn message_builder_quotes_fields() {
+        let message = MessageBuilder::new()
+            .field("processed", 5)
+            .field("name", "two words")
+            .field("quote", "say \"hi\"")
+            .field("empty", "")
+            .field("commands", "a;;b");
+        assert_eq!(
+            message.text(),
+            r#"processed=5 name="two words" quote="say \"hi\"" empty="" commands="a; ;b""#
+        );
+    }
+
+    #[test]
+    fn message_builder_send() {
+        let _env = testing::set_env(&[("QUTE_FIFO", Some("/nonexistent/fifo"))]);
+        let message = MessageBuilder::new()
+            .field("processed", 5)
+            .field("skipped", 2)
+            .field("last", "a b");
+        let (ret, commands) = testing::capture(|| message.send(MsgLevel::Info));
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [r#"message-info processed=5 skipped=2 last="a b""#]
+        );
+    }
+
+    #[test]
+    fn status_skips_repeated_text() {
+        let dir = testing::temp_dir("message-status");
+        let _env = testing::set_env(&[
+            ("QUTE_DATA_DIR", dir.to_str()),
+            ("QUTE_FIFO", Some("/nonexistent/fifo")),
+        ]);
+
+        let (ret, commands) = testing::capture(|| status("downloading"));
+        ret.unwrap();
+        assert_eq!(commands, ["message-info downloading"]);
+
+        let (ret, commands) = testing::capture(|| status("downloading"));
+        ret.unwrap();
+        assert!(commands.is_empty());
+
+        let (ret, commands) = testing::capture(|| status("done"));
+        ret.unwrap();
+        assert_eq!(commands, ["message-info done"]);
+    }
+
+    #[test]
+    fn status_without_fifo() {
+        let dir = testing::temp_dir("message-status-no-fifo");
+        let _env = testing::set_env(&[("QUTE_DATA_DIR", dir.to_str()), ("QUTE_FIFO", None)]);
+        status("printed").unwrap();
+        assert_eq!(
+            state::read(LAST_STATUS).unwrap().as_deref(),
+            Some("printed")
+        );
+    }
+}
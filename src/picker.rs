@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// A program that shows a list of items and lets the user pick one, reading the items
+/// from stdin and writing the chosen one to stdout.
+#[derive(Clone, Debug)]
+pub enum Backend {
+    /// `rofi -dmenu`
+    Rofi,
+    /// `dmenu`
+    Dmenu,
+    /// `wofi --dmenu`
+    Wofi,
+    /// Any other program that works like `dmenu`, given as the program and its
+    /// arguments.
+    ///
+    /// The program should exit with a non-zero status (e.g. 1) if the user cancels.
+    Custom(String, Vec<String>),
+}
+
+impl Backend {
+    /// Returns the name of the program run for this backend.
+    #[inline]
+    pub fn program(&self) -> &str {
+        match self {
+            Backend::Rofi => "rofi",
+            Backend::Dmenu => "dmenu",
+            Backend::Wofi => "wofi",
+            Backend::Custom(program, _) => program,
+        }
+    }
+
+    fn command(&self, prompt: Option<&str>) -> Command {
+        let mut cmd = Command::new(self.program());
+        match self {
+            Backend::Rofi => {
+                cmd.arg("-dmenu");
+            }
+            Backend::Wofi => {
+                cmd.arg("--dmenu");
+            }
+            Backend::Dmenu => {}
+            Backend::Custom(_, args) => {
+                cmd.args(args);
+                return cmd;
+            }
+        }
+        if let Some(prompt) = prompt {
+            cmd.arg("-p").arg(prompt);
+        }
+        cmd
+    }
+}
+
+/// Error returned by [`pick`] when the picker couldn't be run.
+///
+/// [`pick`]: ./fn.pick.html
+#[derive(Debug)]
+pub enum PickerError {
+    /// The program of the backend was not found.
+    NotFound(String),
+    /// Running the program of the backend, or communicating with it, failed.
+    Io(String, io::Error),
+    /// The program of the backend exited with a status other than that for a
+    /// cancellation.
+    Failed(String, ExitStatus),
+}
+
+impl fmt::Display for PickerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PickerError::NotFound(program) => write!(f, "picker {} not found", program),
+            PickerError::Io(program, err) => write!(f, "failed to run picker {}: {}", program, err),
+            PickerError::Failed(program, status) => {
+                write!(f, "picker {} failed ({})", program, status)
+            }
+        }
+    }
+}
+
+impl Error for PickerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PickerError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<PickerError> for io::Error {
+    fn from(err: PickerError) -> io::Error {
+        let kind = match &err {
+            PickerError::NotFound(_) => io::ErrorKind::NotFound,
+            PickerError::Io(_, err) => err.kind(),
+            PickerError::Failed(..) => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Shows `items` with the picker `backend` and returns the item the user picked, or
+/// `None` if they cancelled.
+///
+/// The items are written to the picker one per line, with newlines within them replaced
+/// with spaces. If `prompt` is given, it is passed with `-p` (except to
+/// [`Backend::Custom`], whose arguments are used as is).
+///
+/// `dmenu`-style pickers exit with status 1 when the user presses Escape, so that is
+/// treated as a cancellation, as is picking nothing. Any other failure, including the
+/// program not being installed, is an error. The picked text is returned as written by
+/// the picker, without its trailing newline, so it may not be one of `items` if the
+/// picker lets the user type arbitrary text.
+///
+/// [`Backend::Custom`]: ./enum.Backend.html#variant.Custom
+pub fn pick(
+    items: &[&str],
+    backend: &Backend,
+    prompt: Option<&str>,
+) -> Result<Option<String>, PickerError> {
+    let program = backend.program();
+    let io_err = |err| PickerError::Io(program.into(), err);
+
+    let mut child = backend
+        .command(prompt)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => PickerError::NotFound(program.into()),
+            _ => io_err(err),
+        })?;
+
+    let mut input = String::new();
+    for item in items {
+        input.push_str(&item.replace(['\n', '\r'], " "));
+        input.push('\n');
+    }
+    // Dropping stdin closes it, so the picker knows all items have been written.
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(input.as_bytes()) {
+            // The picker may exit without reading all items, e.g. if the user cancels.
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(io_err(err)),
+            _ => {}
+        }
+    }
+
+    let output = child.wait_with_output().map_err(io_err)?;
+    match output.status.code() {
+        Some(0) => {}
+        Some(1) => return Ok(None),
+        _ => return Err(PickerError::Failed(program.into(), output.status)),
+    }
+
+    let picked = String::from_utf8_lossy(&output.stdout);
+    let picked = picked.trim_end_matches(['\n', '\r']);
+    if picked.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(picked.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Backend {
+        Backend::Custom("sh".into(), vec!["-c".into(), script.into()])
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pick_returns_picked_line() {
+        let picked = pick(&["first", "second\nline", "third"], &sh("sed -n 2p"), None);
+        assert_eq!(picked.unwrap().as_deref(), Some("second line"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pick_cancelled() {
+        assert!(pick(&["item"], &sh("exit 1"), None).unwrap().is_none());
+        assert!(pick(&["item"], &sh("cat >/dev/null"), None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pick_failed() {
+        match pick(&["item"], &sh("exit 2"), None) {
+            Err(PickerError::Failed(program, status)) => {
+                assert_eq!(program, "sh");
+                assert_eq!(status.code(), Some(2));
+            }
+            other => panic!("expected Failed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pick_not_found() {
+        let backend = Backend::Custom("qutescript-nonexistent-picker".into(), Vec::new());
+        match pick(&["item"], &backend, None) {
+            Err(PickerError::NotFound(program)) => {
+                assert_eq!(program, "qutescript-nonexistent-picker")
+            }
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+}
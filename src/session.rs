@@ -0,0 +1,390 @@
+use crate::env;
+use crate::util::{self, escape_arg};
+use crate::yaml;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The name of the session qutebrowser periodically saves the open windows and tabs to.
+pub const AUTOSAVE: &str = "_autosave";
+
+const SESSIONS_DIR: &str = "sessions";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A qutebrowser session, as saved in the `sessions` directory under [`data_dir`].
+///
+/// Only the parts of the session relevant to userscripts are kept.
+///
+/// [`data_dir`]: ../env/fn.data_dir.html
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    pub windows: Vec<Window>,
+}
+
+/// A window of a [`Session`].
+///
+/// [`Session`]: ./struct.Session.html
+#[derive(Clone, Debug, Default)]
+pub struct Window {
+    /// Whether this was the focused window when the session was saved.
+    pub active: bool,
+    pub tabs: Vec<Tab>,
+}
+
+/// A tab of a [`Window`], described by the current entry in its history.
+///
+/// [`Window`]: ./struct.Window.html
+#[derive(Clone, Debug, Default)]
+pub struct Tab {
+    /// Whether this was the focused tab of its window when the session was saved.
+    pub active: bool,
+    pub pinned: bool,
+    pub url: String,
+    pub title: String,
+    /// The horizontal and vertical scroll position of the page, in pixels.
+    pub scroll_pos: (i64, i64),
+}
+
+impl Session {
+    /// Parses the contents of a session file.
+    ///
+    /// The parser only understands the subset of YAML qutebrowser writes session files
+    /// in. Anything it doesn't recognize is ignored rather than treated as an error.
+    pub fn parse(s: &str) -> Session {
+        let root = yaml::parse(s);
+        let windows = root
+            .get("windows")
+            .map(|windows| windows.items().iter().map(Window::from_yaml).collect())
+            .unwrap_or_default();
+        Session { windows }
+    }
+
+    /// Returns the window that was focused when the session was saved.
+    #[inline]
+    pub fn active_window(&self) -> Option<&Window> {
+        self.windows.iter().find(|window| window.active)
+    }
+
+    /// Returns the focused tab of the focused window.
+    #[inline]
+    pub fn active_tab(&self) -> Option<&Tab> {
+        self.active_window().and_then(Window::active_tab)
+    }
+
+    /// Returns all tabs of all windows, in order, with their positions.
+    pub fn tabs(&self) -> Vec<TabRef> {
+        self.windows
+            .iter()
+            .enumerate()
+            .flat_map(|(win_idx, window)| {
+                window
+                    .tabs
+                    .iter()
+                    .enumerate()
+                    .map(move |(tab_idx, tab)| TabRef {
+                        window: win_idx,
+                        window_active: window.active,
+                        index: tab_idx + 1,
+                        tab: tab.clone(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A tab of a [`Session`] together with its position, which is enough to focus it.
+///
+/// [`Session`]: ./struct.Session.html
+#[derive(Clone, Debug)]
+pub struct TabRef {
+    /// The position of the tab's window in the session, starting at 0.
+    pub window: usize,
+    /// Whether the tab's window was focused when the session was saved.
+    pub window_active: bool,
+    /// The 1-based index of the tab in its window, as used by `tab-focus`.
+    pub index: usize,
+    pub tab: Tab,
+}
+
+impl TabRef {
+    /// Returns the command that focuses the tab.
+    ///
+    /// This is `tab-focus {index}` for a tab in the focused window, and
+    /// `tab-select {window}/{index}` otherwise, with the position of the window in the
+    /// session as its id. That only matches qutebrowser's window id if no earlier window
+    /// has been closed since qutebrowser started.
+    pub fn focus_command(&self) -> String {
+        if self.window_active {
+            format!("tab-focus {}", self.index)
+        } else {
+            format!("tab-select {}/{}", self.window, self.index)
+        }
+    }
+
+    /// Focuses the tab; see [`focus_command`].
+    ///
+    /// [`focus_command`]: #method.focus_command
+    #[inline]
+    pub fn focus(&self) -> Result<(), io::Error> {
+        util::send_command(&self.focus_command())
+    }
+}
+
+/// Returns the tabs in the session qutebrowser last autosaved whose title or URL match
+/// `query`, best matches first.
+///
+/// Tabs containing `query` as a substring, ignoring case, come first, followed by tabs
+/// containing the characters of `query` in order (e.g. `ghqb` matches
+/// `github.com/qutebrowser`). Within each group, tabs are in session order. An empty query
+/// matches every tab. The autosaved session may lag behind the browser by a few seconds;
+/// see [`load_autosave`].
+///
+/// [`load_autosave`]: ./fn.load_autosave.html
+pub fn find_tabs(query: &str) -> Result<Vec<TabRef>, io::Error> {
+    let query = query.to_lowercase();
+    let tabs = load_autosave()?.tabs();
+
+    let haystacks = |tab_ref: &TabRef| {
+        [
+            tab_ref.tab.title.to_lowercase(),
+            tab_ref.tab.url.to_lowercase(),
+        ]
+    };
+    let (substring, rest): (Vec<_>, Vec<_>) = tabs.into_iter().partition(|tab_ref| {
+        haystacks(tab_ref)
+            .iter()
+            .any(|haystack| haystack.contains(&query))
+    });
+    let fuzzy = rest.into_iter().filter(|tab_ref| {
+        haystacks(tab_ref).iter().any(|haystack| {
+            let mut chars = haystack.chars();
+            query.chars().all(|c| chars.any(|h| h == c))
+        })
+    });
+    Ok(substring.into_iter().chain(fuzzy).collect())
+}
+
+impl Window {
+    fn from_yaml(value: &yaml::Value) -> Window {
+        Window {
+            active: value.get_bool("active"),
+            tabs: value
+                .get("tabs")
+                .map(|tabs| tabs.items().iter().map(Tab::from_yaml).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the focused tab of this window.
+    #[inline]
+    pub fn active_tab(&self) -> Option<&Tab> {
+        self.tabs.iter().find(|tab| tab.active)
+    }
+}
+
+impl Tab {
+    fn from_yaml(value: &yaml::Value) -> Tab {
+        let history = value.get("history").map(yaml::Value::items).unwrap_or(&[]);
+        let entry = history
+            .iter()
+            .find(|entry| entry.get_bool("active"))
+            .or_else(|| history.last());
+
+        let mut tab = Tab {
+            active: value.get_bool("active"),
+            ..Tab::default()
+        };
+        if let Some(entry) = entry {
+            tab.pinned = entry.get_bool("pinned");
+            tab.url = entry.get_str("url").unwrap_or_default().into();
+            tab.title = entry.get_str("title").unwrap_or_default().into();
+            if let Some(pos) = entry.get("scroll-pos") {
+                let coord = |key| pos.get_str(key).and_then(|v| v.parse().ok());
+                tab.scroll_pos = (coord("x").unwrap_or(0), coord("y").unwrap_or(0));
+            }
+        }
+        tab
+    }
+}
+
+/// Returns the path of the file for the session `name`.
+#[inline]
+pub fn path(name: &str) -> PathBuf {
+    env::data_dir()
+        .join(SESSIONS_DIR)
+        .join(format!("{}.yml", name))
+}
+
+/// Reads and parses the session `name`.
+#[inline]
+pub fn load(name: &str) -> Result<Session, io::Error> {
+    let contents = fs::read_to_string(path(name))?;
+    Ok(Session::parse(&contents))
+}
+
+/// Reads and parses the session qutebrowser last autosaved.
+///
+/// qutebrowser only autosaves periodically, so this may lag behind the current state of
+/// the browser by a few seconds; use [`save`] for an up-to-date session.
+///
+/// [`save`]: ./fn.save.html
+#[inline]
+pub fn load_autosave() -> Result<Session, io::Error> {
+    load(AUTOSAVE)
+}
+
+/// Makes qutebrowser save the focused window to the session `name`, then waits up to
+/// `timeout` for the file to be written and parses it.
+///
+/// This sends `session-save --quiet --force --only-active-window {name}`. It fails
+/// immediately where the `FIFO` isn't a named pipe (see [`fifo_is_pipe`]).
+///
+/// [`fifo_is_pipe`]: ../env/fn.fifo_is_pipe.html
+pub fn save(name: &str, timeout: Duration) -> Result<Session, io::Error> {
+    if !env::fifo_is_pipe() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sessions can't be saved while the userscript is running on this platform",
+        ));
+    }
+
+    let path = path(name);
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let message = format!(
+        "session-save --quiet --force --only-active-window {}",
+        escape_arg(name)
+    );
+    util::send_command(&message)?;
+
+    // qutebrowser writes session files atomically, so the file is complete once it exists.
+    let deadline = Instant::now() + timeout;
+    loop {
+        match fs::read_to_string(&path) {
+            Ok(contents) => return Ok(Session::parse(&contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("session {} was not saved", name),
+            ));
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn parse_session() {
+        let session = Session::parse(testing::SESSION);
+        assert_eq!(session.windows.len(), 2);
+        assert!(!session.windows[0].active);
+        assert_eq!(session.windows[0].tabs.len(), 1);
+        assert_eq!(session.windows[0].tabs[0].url, "https://doc.rust-lang.org/");
+
+        let window = session.active_window().unwrap();
+        let titles: Vec<_> = window.tabs.iter().map(|tab| tab.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "Inbox",
+                "qutebrowser/qutebrowser: A keyboard-driven browser",
+                "It's a \"quoted\" title",
+                "Music",
+                "Q&A",
+            ]
+        );
+        let pinned: Vec<_> = window.tabs.iter().map(|tab| tab.pinned).collect();
+        assert_eq!(pinned, [true, false, false, true, false]);
+
+        // The active history entry is used, not the last one.
+        assert_eq!(
+            window.tabs[1].url,
+            "https://github.com/qutebrowser/qutebrowser"
+        );
+        assert_eq!(window.tabs[1].scroll_pos, (10, 640));
+
+        let active = session.active_tab().unwrap();
+        assert_eq!(active.url, "https://example.com/it's");
+        assert_eq!(active.scroll_pos, (0, 0));
+    }
+
+    #[test]
+    fn parse_session_falls_back_to_last_history_entry() {
+        let session = Session::parse(
+            "windows:\n\
+             - tabs:\n  \
+               - history:\n    \
+                 - url: https://first.example/\n    \
+                 - url: https://last.example/\n",
+        );
+        assert_eq!(session.windows[0].tabs[0].url, "https://last.example/");
+        assert!(Session::parse("").windows.is_empty());
+    }
+
+    #[test]
+    fn tab_refs_focus_commands() {
+        let tabs = Session::parse(testing::SESSION).tabs();
+        let commands: Vec<_> = tabs.iter().map(TabRef::focus_command).collect();
+        assert_eq!(
+            commands,
+            [
+                "tab-select 0/1",
+                "tab-focus 1",
+                "tab-focus 2",
+                "tab-focus 3",
+                "tab-focus 4",
+                "tab-focus 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn find_tabs_ranks_substring_matches_first() {
+        let _env = testing::autosave("session-find-tabs", testing::SESSION);
+
+        // The Q&A tab's URL contains "qa"; the GitHub tab before it only has a "q"
+        // followed later by an "a" in its title.
+        let urls: Vec<_> = find_tabs("QA")
+            .unwrap()
+            .into_iter()
+            .map(|tab_ref| tab_ref.tab.url)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://qa.example.com/",
+                "https://github.com/qutebrowser/qutebrowser",
+            ]
+        );
+
+        let found = find_tabs("rustdoc").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].focus_command(), "tab-select 0/1");
+        assert!(find_tabs("xyzzy").unwrap().is_empty());
+        assert_eq!(find_tabs("").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn find_tabs_without_autosave() {
+        let dir = testing::temp_dir("session-find-tabs-missing");
+        let _env = testing::set_env(&[("QUTE_DATA_DIR", dir.to_str())]);
+        let err = find_tabs("anything").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,24 @@
+use crate::env;
+
+use std::fs;
+use std::io;
+
+/// Reads the state file `name` under `env::data_path`, returning `None` if it doesn't
+/// exist.
+pub fn read(name: &str) -> Result<Option<String>, io::Error> {
+    match fs::read_to_string(env::data_path(name)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the state file `name` under `env::data_path`, creating its directory if
+/// necessary.
+pub fn write(name: &str, contents: &str) -> Result<(), io::Error> {
+    let path = env::data_path(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
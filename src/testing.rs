@@ -0,0 +1,168 @@
+//! Helpers shared by the unit tests.
+
+use crate::util;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Mutex, MutexGuard};
+
+/// Serializes tests that change process-wide state: environment variables, the command
+/// history, and the command size warning.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Locks [`ENV_LOCK`] for the rest of the test, even if another test panicked with it.
+pub fn lock_env() -> MutexGuard<'static, ()> {
+    ENV_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Restores environment variables to their previous values when dropped.
+pub struct EnvGuard {
+    saved: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, value) in self.saved.drain(..) {
+            match value {
+                Some(value) => env::set_var(&key, value),
+                None => env::remove_var(&key),
+            }
+        }
+    }
+}
+
+/// Locks the environment and sets each variable in `vars` to its value, or removes it if
+/// the value is `None`, until the returned guard is dropped.
+pub fn set_env(vars: &[(&str, Option<&str>)]) -> EnvGuard {
+    let lock = lock_env();
+    let mut saved = Vec::with_capacity(vars.len());
+    for (key, value) in vars {
+        saved.push((key.to_string(), env::var_os(key)));
+        match value {
+            Some(value) => env::set_var(key, value),
+            None => env::remove_var(key),
+        }
+    }
+    EnvGuard { saved, _lock: lock }
+}
+
+/// Runs `f` with commands batched and logged, and returns its result along with the
+/// commands it sent. The batch is discarded instead of being written to the `FIFO`.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    util::begin_batch();
+    util::start_command_log();
+    let ret = f();
+    let commands = util::take_command_log()
+        .map(|log| log.commands().to_vec())
+        .unwrap_or_default();
+    util::discard_batch();
+    (ret, commands)
+}
+
+/// Returns a new, empty directory for the test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("qutescript-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `contents` as the autosaved session of a new data directory for the test `name`
+/// and points `QUTE_DATA_DIR` at it until the returned guard is dropped.
+pub fn autosave(name: &str, contents: &str) -> EnvGuard {
+    let dir = temp_dir(name);
+    fs::create_dir_all(dir.join("sessions")).unwrap();
+    fs::write(dir.join("sessions").join("_autosave.yml"), contents).unwrap();
+    set_env(&[("QUTE_DATA_DIR", dir.to_str())])
+}
+
+/// A session file as qutebrowser writes it, with an inactive window with one tab and a
+/// focused window with five: a pinned tab, a tab with two history entries, the focused
+/// tab, another pinned tab, and a last unpinned tab.
+pub const SESSION: &str = r#"windows:
+- geometry: !!binary |
+    AdnQywADAAAAAAAAAAAAFAAAB38AAAQ3AAAAAAAAABQAAAd/AAAENwAAAAAAAAAAB4AAAAAAAAAA
+    FAAAB38AAAQ3
+  tabs:
+  - active: true
+    history:
+    - active: true
+      last_visited: '2024-05-01T09:30:00'
+      pinned: false
+      scroll-pos:
+        x: 0
+        y: 0
+      title: Rust Documentation
+      url: https://doc.rust-lang.org/
+      zoom: 1.0
+- active: true
+  geometry: !!binary |
+    AdnQywADAAAAAAAAAAAAFAAAB38AAAQ3AAAAAAAAABQAAAd/AAAENwAAAAAAAAAAB4AAAAAAAAAA
+    FAAAB38AAAQ3
+  tabs:
+  - history:
+    - active: true
+      last_visited: '2024-05-01T09:31:00'
+      pinned: true
+      scroll-pos:
+        x: 0
+        y: 0
+      title: Inbox
+      url: https://mail.example.com/
+      zoom: 1.0
+  - history:
+    - last_visited: '2024-05-01T09:32:00'
+      pinned: false
+      scroll-pos:
+        x: 0
+        y: 300
+      title: qutebrowser at DuckDuckGo
+      url: https://duckduckgo.com/?q=qutebrowser
+      zoom: 1.0
+    - active: true
+      last_visited: '2024-05-01T09:33:00'
+      pinned: false
+      scroll-pos:
+        x: 10
+        y: 640
+      title: 'qutebrowser/qutebrowser: A keyboard-driven browser'
+      url: https://github.com/qutebrowser/qutebrowser
+      zoom: 1.0
+  - active: true
+    history:
+    - active: true
+      last_visited: '2024-05-01T09:34:00'
+      pinned: false
+      scroll-pos:
+        x: 0
+        y: 0
+      title: 'It''s a "quoted" title'
+      url: https://example.com/it's
+      zoom: 1.25
+  - history:
+    - active: true
+      last_visited: '2024-05-01T09:35:00'
+      pinned: true
+      scroll-pos:
+        x: 0
+        y: 0
+      title: Music
+      url: https://music.example.com/
+      zoom: 1.0
+  - history:
+    - active: true
+      last_visited: '2024-05-01T09:36:00'
+      pinned: false
+      scroll-pos:
+        x: 0
+        y: 0
+      title: Q&A
+      url: https://qa.example.com/
+      zoom: 1.0
+"#;
@@ -1,34 +1,2732 @@
+use crate::config;
+use crate::download;
 use crate::env;
+use crate::session;
+use crate::state;
 
-use std::io;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub enum Mode {
     Normal,
     Insert,
     Caret,
     Passthrough,
+    Command,
+}
+
+impl Mode {
+    /// Returns the name of the mode as accepted by qutebrowser.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::Caret => "caret",
+            Mode::Passthrough => "passthrough",
+            Mode::Command => "command",
+        }
+    }
+
+    /// Returns whether the mode can be entered with `enter-mode`.
+    ///
+    /// qutebrowser refuses to enter modes that depend on some other state with
+    /// `enter-mode`: command mode needs the command line to be set up (see
+    /// [`enter_command_mode`]), and the hint, prompt, yes/no, and register modes need a
+    /// pending interaction. Of the modes here, only `Command` is not enterable.
+    ///
+    /// [`enter_command_mode`]: ./fn.enter_command_mode.html
+    #[inline]
+    pub fn is_enterable(&self) -> bool {
+        !matches!(self, Mode::Command)
+    }
+}
+
+/// A command that can be sent to qutebrowser.
+pub enum Command {
+    /// `enter-mode {mode}`
+    EnterMode(Mode),
+    /// `fake-key [--global] {keys}`
+    FakeKey { keys: String, global: bool },
+    /// A command emitted verbatim.
+    ///
+    /// The contents are not escaped in any way; the caller is responsible for quoting
+    /// arguments as qutebrowser expects. Use this for commands not otherwise modeled by
+    /// this enum.
+    Raw(String),
+}
+
+impl Command {
+    /// Sends the command to qutebrowser.
+    #[inline]
+    pub fn execute(&self) -> Result<(), io::Error> {
+        send_command(&self.to_string())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::EnterMode(mode) => write!(f, "enter-mode {}", mode.as_str()),
+            Command::FakeKey { keys, global } => {
+                let flag = if *global { " --global" } else { "" };
+                write!(f, "fake-key{} {}", flag, keys)
+            }
+            Command::Raw(cmd) => f.write_str(cmd),
+        }
+    }
 }
 
 /// Sends the command `enter-mode {mode}` to qutebrowser to enter the specified mode.
+///
+/// Returns an error of kind `InvalidInput` without sending anything if the mode can't be
+/// entered with `enter-mode` (see [`Mode::is_enterable`]).
+///
+/// [`Mode::is_enterable`]: ./enum.Mode.html#method.is_enterable
 pub fn enter_mode(mode: Mode) -> Result<(), io::Error> {
-    let mode_str = match mode {
-        Mode::Normal => "normal",
-        Mode::Insert => "insert",
-        Mode::Caret => "caret",
-        Mode::Passthrough => "passthrough",
+    if !mode.is_enterable() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} mode can't be entered with enter-mode", mode.as_str()),
+        ));
+    }
+    Command::EnterMode(mode).execute()
+}
+
+const PASSTHROUGH_STATE: &str = "passthrough";
+
+/// Toggles between passthrough and normal mode, remembering the mode entered across
+/// invocations.
+///
+/// The mode last entered by this function is stored in a state file under [`data_dir`]:
+/// if it was passthrough mode, normal mode is entered, and otherwise passthrough mode
+/// is. Mode changes made by other means (e.g. pressing Escape to leave passthrough mode)
+/// aren't tracked.
+///
+/// [`data_dir`]: ../env/fn.data_dir.html
+pub fn toggle_passthrough() -> Result<(), io::Error> {
+    let in_passthrough =
+        state::read(PASSTHROUGH_STATE)?.as_deref() == Some(Mode::Passthrough.as_str());
+    let mode = if in_passthrough {
+        Mode::Normal
+    } else {
+        Mode::Passthrough
+    };
+
+    let mode_str = mode.as_str();
+    enter_mode(mode)?;
+    state::write(PASSTHROUGH_STATE, mode_str)
+}
+
+/// Sends text to qutebrowser as raw text input (`fake-key [--global] {string}`).
+///
+/// If `global` is `true`, the keys are sent to qutebrowser itself rather than the
+/// focused element, so they trigger key bindings instead of typing into the page.
+///
+/// qutebrowser silently ignores malformed keys, so `s` is first checked with
+/// [`validate_keys`], and an error of kind `InvalidInput` is returned without sending
+/// anything if it fails.
+///
+/// [`validate_keys`]: ./fn.validate_keys.html
+pub fn fake_key(s: &str, global: bool) -> Result<(), io::Error> {
+    validate_keys(s)?;
+    Command::FakeKey {
+        keys: s.into(),
+        global,
+    }
+    .execute()
+}
+
+/// The modifiers qutebrowser accepts in special keys like `<Ctrl-x>`, lowercase.
+const KEY_MODIFIERS: &[&str] = &[
+    "ctrl", "control", "alt", "altgr", "shift", "meta", "mod1", "mod4", "super", "cmd", "command",
+    "windows", "num",
+];
+
+/// Checks that `s` is well-formed in qutebrowser's key syntax.
+///
+/// Keys are either single characters or special keys in angle brackets, like `<Escape>`
+/// or `<Ctrl-Shift-t>`, where every part before the key itself must be a known modifier
+/// (`Ctrl`, `Alt`, `AltGr`, `Shift`, `Meta`, `Num`, and their aliases, in any case).
+/// Modifiers may be separated with `-` or `+`, as in `<Num+5>`. The names of special
+/// keys aren't checked, only that each `<` is closed by a `>` before the next `<` and that
+/// the brackets aren't empty. qutebrowser types an unclosed `<` literally, but as that is
+/// usually a mistake, it is rejected here; use `<less>` for a literal `<`.
+pub fn validate_keys(s: &str) -> Result<(), InvalidKeys> {
+    let invalid = |reason: &str| InvalidKeys {
+        keys: s.into(),
+        reason: reason.into(),
     };
 
-    let message = format!("enter-mode {}", mode_str);
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        let special = &rest[start + 1..];
+        let end = special
+            .find(['<', '>'])
+            .filter(|&end| special[end..].starts_with('>'))
+            .ok_or_else(|| invalid("'<' without a closing '>'"))?;
+        let special_key = &special[..end];
+
+        // The key itself may be a separator, as in `<Ctrl-->` or `<Ctrl++>`.
+        let is_separator = |c: char| c == '-' || c == '+';
+        let (modifiers, key) = match special_key.char_indices().rev().nth(1) {
+            Some((idx, c)) if is_separator(c) && special_key.ends_with(is_separator) => {
+                (&special_key[..idx], &special_key[idx + 1..])
+            }
+            _ => special_key
+                .rsplit_once(is_separator)
+                .unwrap_or(("", special_key)),
+        };
+        if key.is_empty() {
+            return Err(invalid("empty key in '<>'"));
+        }
+        if !modifiers.is_empty() {
+            let unknown = modifiers.split(is_separator).find(|modifier| {
+                !KEY_MODIFIERS
+                    .iter()
+                    .any(|known| modifier.eq_ignore_ascii_case(known))
+            });
+            if let Some(modifier) = unknown {
+                return Err(invalid(&format!("unknown modifier '{}'", modifier)));
+            }
+        }
+
+        rest = &special[end + 1..];
+    }
+    Ok(())
+}
+
+/// Error returned by [`validate_keys`] for a malformed key sequence.
+///
+/// [`validate_keys`]: ./fn.validate_keys.html
+#[derive(Clone, Debug)]
+pub struct InvalidKeys {
+    pub keys: String,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid keys {:?}: {}", self.keys, self.reason)
+    }
+}
+
+impl Error for InvalidKeys {}
+
+impl From<InvalidKeys> for io::Error {
+    #[inline]
+    fn from(err: InvalidKeys) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// Sends keys to qutebrowser itself rather than the focused element
+/// (`fake-key --global {string}`).
+#[inline]
+pub fn fake_key_global(s: &str) -> Result<(), io::Error> {
+    fake_key(s, true)
+}
+
+/// Binds `key` to `command` in the given mode, or normal mode if `mode` is `None`; see
+/// [`config::bind`].
+///
+/// [`config::bind`]: ../config/fn.bind.html
+#[inline]
+pub fn bind(key: &str, command: &str, mode: Option<Mode>) -> Result<(), io::Error> {
+    config::bind(key, command, mode)
+}
+
+/// Unbinds `key` in the given mode, or normal mode if `mode` is `None`; see
+/// [`config::unbind`].
+///
+/// [`config::unbind`]: ../config/fn.unbind.html
+#[inline]
+pub fn unbind(key: &str, mode: Option<Mode>) -> Result<(), io::Error> {
+    config::unbind(key, mode)
+}
+
+#[inline]
+pub(crate) fn mode_flag(mode: Option<Mode>) -> String {
+    match mode {
+        Some(mode) => format!(" --mode {}", mode.as_str()),
+        None => String::new(),
+    }
+}
+
+/// The level of a message shown in qutebrowser's status bar.
+#[derive(Clone, Copy, Debug)]
+pub enum MsgLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl MsgLevel {
+    /// Returns the name of the qutebrowser command that shows a message of this level.
+    #[inline]
+    pub fn command(&self) -> &'static str {
+        match self {
+            MsgLevel::Info => "message-info",
+            MsgLevel::Warning => "message-warning",
+            MsgLevel::Error => "message-error",
+        }
+    }
+}
+
+/// Sends the command `message-{level} {text}` to qutebrowser to show a message of the
+/// given level.
+///
+/// Newlines in `text` are replaced with spaces and `;;` is broken up, so the text can't
+/// end the command or start another one.
+pub fn message(level: MsgLevel, text: &str) -> Result<(), io::Error> {
+    let message = format!("{} {}", level.command(), escape_rest(text));
+    send_command(&message)
+}
+
+/// Shows an info message in qutebrowser (`message-info {text}`).
+#[inline]
+pub fn message_info(text: &str) -> Result<(), io::Error> {
+    message(MsgLevel::Info, text)
+}
+
+/// The separator [`message_info_multi`] joins lines with.
+///
+/// [`message_info_multi`]: ./fn.message_info_multi.html
+pub const MESSAGE_SEPARATOR: &str = " | ";
+
+/// Shows `lines` as a single info message, joined with [`MESSAGE_SEPARATOR`]
+/// (`message-info {line} | {line}...`).
+///
+/// Sending one message per line makes the status bar flicker as messages stack up; the
+/// status bar shows a message on one line, so joining them keeps it to a single update.
+/// Newlines within the lines are replaced with spaces, and empty lines are skipped.
+///
+/// [`MESSAGE_SEPARATOR`]: ./constant.MESSAGE_SEPARATOR.html
+pub fn message_info_multi(lines: &[&str]) -> Result<(), io::Error> {
+    let text = lines
+        .iter()
+        .map(|line| escape_rest(line.trim()))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(MESSAGE_SEPARATOR);
+    message_info(&text)
+}
+
+/// Shows a warning message in qutebrowser (`message-warning {text}`).
+#[inline]
+pub fn message_warning(text: &str) -> Result<(), io::Error> {
+    message(MsgLevel::Warning, text)
+}
+
+/// Shows an error message in qutebrowser (`message-error {text}`).
+#[inline]
+pub fn message_error(text: &str) -> Result<(), io::Error> {
+    message(MsgLevel::Error, text)
+}
+
+/// Shows an error message in qutebrowser with the error of `result`, if any, then returns
+/// `result` unchanged.
+///
+/// The message is shown with [`message::error`], so it is printed to stderr if
+/// `QUTE_FIFO` isn't set. If showing it fails too (e.g. because the `FIFO` itself can't
+/// be written to), that failure is ignored and the original error is returned.
+///
+/// [`message::error`]: ../message/fn.error.html
+pub fn report_on_error<T>(result: Result<T, io::Error>) -> Result<T, io::Error> {
+    if let Err(err) = &result {
+        let _ = crate::message::error(&err.to_string());
+    }
+    result
+}
+
+/// Inserts text at the cursor position in the focused field (`insert-text {text}`).
+///
+/// A command can't span lines, so newlines in `text` are inserted as spaces, and `;;` is
+/// broken up into `; ;` so it doesn't start a new command.
+pub fn insert_text(text: &str) -> Result<(), io::Error> {
+    let message = format!("insert-text {}", escape_rest(text));
     send_command(&message)
 }
 
-/// Sends text to qutebrowser as raw text input (`fake-key {string}`).
-pub fn fake_key(s: &str) -> Result<(), io::Error> {
-    let message = format!("fake-key {}", s);
+/// Inserts text at the cursor position in the focused field, then moves the cursor left
+/// by `cursor_from_end` characters.
+///
+/// This sends `insert-text {text} ;; fake-key <Left><Left>...`. Newlines in `text` are
+/// inserted as spaces, as with [`insert_text`]. Breaking up `;;` would move the cursor to
+/// the wrong position, so an error of kind `InvalidInput` is returned if `text` contains
+/// it.
+///
+/// [`insert_text`]: ./fn.insert_text.html
+pub fn insert_text_with_cursor(text: &str, cursor_from_end: usize) -> Result<(), io::Error> {
+    if text.contains(";;") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "text to insert with a cursor position can't contain ';;'",
+        ));
+    }
+    if cursor_from_end == 0 {
+        return insert_text(text);
+    }
+
+    let message = format!(
+        "insert-text {} ;; fake-key {}",
+        escape_rest(text),
+        "<Left>".repeat(cursor_from_end)
+    );
+    send_command(&message)
+}
+
+/// Opens qutebrowser's command line with `text` prefilled (`cmd-set-text {text}`).
+///
+/// `text` should usually start with `:`. The command was named `set-cmd-text` before
+/// qutebrowser v3.0. Newlines in `text` are replaced with spaces and `;;` is broken up,
+/// since either would end up outside the command line.
+pub fn set_cmd_text(text: &str) -> Result<(), io::Error> {
+    let message = format!("cmd-set-text {}", escape_rest(text));
+    send_command(&message)
+}
+
+/// Opens qutebrowser's command line with `:open {url}` prefilled with the URL of the
+/// current page, so the user can edit it and navigate (`cmd-set-text :open {url}`).
+///
+/// The URL isn't quoted: both `cmd-set-text` and `open` take the rest of the line
+/// verbatim, so quotes would end up in the URL.
+pub fn edit_current_url_in_cmdline() -> Result<(), io::Error> {
+    set_cmd_text(&format!(":open {}", env::current_url()))
+}
+
+/// Enters command mode, with `prefill` in the command line if given.
+///
+/// qutebrowser doesn't allow entering command mode with `enter-mode`, so this sends
+/// `cmd-set-text :{prefill}` instead. A leading `:` in `prefill` is optional.
+pub fn enter_command_mode(prefill: Option<&str>) -> Result<(), io::Error> {
+    let prefill = prefill.unwrap_or_default();
+    let prefill = prefill.strip_prefix(':').unwrap_or(prefill);
+    set_cmd_text(&format!(":{}", prefill))
+}
+
+/// Asks the user to confirm closing the current tab.
+///
+/// This sends `cmd-set-text :tab-close`, prefilling the command line with `:tab-close`;
+/// the tab is closed once the user presses Enter, and left open if they press Escape.
+#[inline]
+pub fn tab_close_confirm() -> Result<(), io::Error> {
+    set_cmd_text(":tab-close")
+}
+
+const CLOSED_TABS_STATE: &str = "closed-tabs";
+
+/// The maximum number of URLs [`tab_close_remembered`] keeps.
+///
+/// [`tab_close_remembered`]: ./fn.tab_close_remembered.html
+pub const MAX_CLOSED_TABS: usize = 50;
+
+/// Closes the current tab (`tab-close`), remembering its URL for [`reopen_last_closed`].
+///
+/// Unlike qutebrowser's own `undo`, the list of closed tabs is kept in a state file under
+/// [`data_dir`], so it persists across restarts. Only the last [`MAX_CLOSED_TABS`] URLs
+/// are kept. If there is no current URL, the tab is closed without being remembered.
+///
+/// [`reopen_last_closed`]: ./fn.reopen_last_closed.html
+/// [`data_dir`]: ../env/fn.data_dir.html
+/// [`MAX_CLOSED_TABS`]: ./constant.MAX_CLOSED_TABS.html
+pub fn tab_close_remembered() -> Result<(), io::Error> {
+    if let Some(url) = env::url_opt() {
+        let mut urls = closed_tabs()?;
+        urls.push(url);
+        let excess = urls.len().saturating_sub(MAX_CLOSED_TABS);
+        write_closed_tabs(&urls[excess..])?;
+    }
+    send_command("tab-close")
+}
+
+/// Opens the URL most recently closed with [`tab_close_remembered`] in a new tab and
+/// removes it from the list, returning the URL, or `None` if the list is empty.
+///
+/// [`tab_close_remembered`]: ./fn.tab_close_remembered.html
+pub fn reopen_last_closed() -> Result<Option<String>, io::Error> {
+    let mut urls = closed_tabs()?;
+    let url = match urls.pop() {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    write_closed_tabs(&urls)?;
+    Open::new().tab(true).send(&url)?;
+    Ok(Some(url))
+}
+
+fn closed_tabs() -> Result<Vec<String>, io::Error> {
+    Ok(state::read(CLOSED_TABS_STATE)?
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn write_closed_tabs(urls: &[String]) -> Result<(), io::Error> {
+    let mut contents = urls.join("\n");
+    contents.push('\n');
+    state::write(CLOSED_TABS_STATE, &contents)
+}
+
+/// What to yank with the `yank` command.
+pub enum Yank {
+    /// The URL of the current page.
+    Url,
+    /// The URL of the current page, decoded for display.
+    PrettyUrl,
+    /// The title of the current page.
+    Title,
+    /// The scheme, domain, and port of the current page.
+    Domain,
+    /// The text currently selected on the page.
+    Selection,
+    /// The title and URL of the current page as a Markdown link.
+    Markdown,
+    /// The given text. Newlines in it are yanked as spaces, and `;;` is broken up, since
+    /// either would end the command.
+    Inline(String),
+}
+
+impl fmt::Display for Yank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Yank::Url => f.write_str("url"),
+            Yank::PrettyUrl => f.write_str("pretty-url"),
+            Yank::Title => f.write_str("title"),
+            Yank::Domain => f.write_str("domain"),
+            Yank::Selection => f.write_str("selection"),
+            Yank::Markdown => f.write_str("markdown"),
+            Yank::Inline(text) => write!(f, "inline {}", escape_rest(text)),
+        }
+    }
+}
+
+/// Yanks something to the clipboard, or to the primary selection if `sel` is `true`
+/// (`yank [--sel] {what}`).
+pub fn yank(what: Yank, sel: bool) -> Result<(), io::Error> {
+    let flag = if sel { " --sel" } else { "" };
+    let message = format!("yank{} {}", flag, what);
     send_command(&message)
 }
 
+/// Which selection to put text on with [`set_clipboard`].
+///
+/// [`set_clipboard`]: ./fn.set_clipboard.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardSel {
+    /// The system clipboard.
+    Clipboard,
+    /// The primary selection, on platforms that have one (see [`default_selection`]).
+    ///
+    /// [`default_selection`]: ./fn.default_selection.html
+    Primary,
+}
+
+/// Puts `text` on the clipboard or primary selection (`yank [--sel] inline {text}`).
+///
+/// qutebrowser copies the text itself, so this works on every platform without a page
+/// selection, clipboard tool, or clipboard access for JavaScript. `yank inline` takes its
+/// text as the rest of the command line, so newlines in `text` are replaced with spaces
+/// and `;;` is broken up.
+#[inline]
+pub fn set_clipboard(text: &str, sel: ClipboardSel) -> Result<(), io::Error> {
+    yank(Yank::Inline(text.into()), sel == ClipboardSel::Primary)
+}
+
+/// Yanks the current page as a Markdown link, `[{title}]({url})`, to the clipboard, or to
+/// the primary selection if `sel` is `true` (`yank [--sel] inline {link}`).
+///
+/// Characters in the title that would end the link text or format it (`` \`*_[]<> ``)
+/// are backslash-escaped, and spaces and parentheses in the URL are percent-encoded.
+///
+/// An error of kind `NotFound` is returned if there is no current URL or `QUTE_TITLE` is
+/// not set, and of kind `InvalidData` if the title is not valid unicode.
+pub fn yank_markdown_link(sel: bool) -> Result<(), io::Error> {
+    let url =
+        env::url_opt().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no URL to yank"))?;
+    let title = env::try_var("QUTE_TITLE").map_err(|err| {
+        let kind = match err {
+            env::EnvError::Missing(_) => io::ErrorKind::NotFound,
+            env::EnvError::NotUnicode(..) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    })?;
+    let link = format!(
+        "[{}]({})",
+        escape_markdown(&title),
+        url.replace(' ', "%20")
+            .replace('(', "%28")
+            .replace(')', "%29")
+    );
+    yank(Yank::Inline(link), sel)
+}
+
+/// Converts the HTML of the element selected via hints to Markdown and yanks it to the
+/// clipboard, or to the primary selection if `sel` is `true` (`yank [--sel] inline
+/// {markdown}`).
+///
+/// This is meant for userscripts spawned with `hint all userscript ...` or
+/// similar, clipping formatted content from a page. `yank inline` takes its text as the
+/// rest of the command line, so the lines of the Markdown are joined with spaces; block
+/// structure such as lists and paragraphs is lost, while inline formatting and links
+/// remain.
+///
+/// If there is no selected HTML, a warning message is shown instead and nothing is
+/// yanked.
+#[cfg(feature = "html2md")]
+pub fn yank_selection_as_markdown(sel: bool) -> Result<(), io::Error> {
+    let html = env::try_var("QUTE_SELECTED_HTML").unwrap_or_default();
+    let markdown = html2md::parse_html(&html);
+    let markdown = markdown.trim();
+    if markdown.is_empty() {
+        return message_warning("No HTML selected to convert to Markdown");
+    }
+
+    yank(Yank::Inline(markdown.into()), sel)
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Yanks something to the platform's default selection, as given by
+/// [`default_selection`].
+///
+/// [`default_selection`]: ./fn.default_selection.html
+#[inline]
+pub fn yank_default(what: Yank) -> Result<(), io::Error> {
+    yank(what, default_selection())
+}
+
+/// Returns whether the primary selection is the sensible default to yank to and paste
+/// from on the current platform.
+///
+/// This is `true` on Unix platforms other than macOS, where X11 and Wayland provide a
+/// primary selection, and `false` on Windows and macOS, which only have a clipboard.
+#[inline]
+pub fn default_selection() -> bool {
+    cfg!(all(unix, not(target_os = "macos")))
+}
+
+/// The group of elements to select with hints.
+pub enum HintGroup {
+    All,
+    Links,
+    Images,
+    Inputs,
+    /// Elements with a URL, such as links, images, and frames.
+    Url,
+    /// A group defined in the `hints.selectors` setting.
+    Custom(String),
+}
+
+impl fmt::Display for HintGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HintGroup::All => f.write_str("all"),
+            HintGroup::Links => f.write_str("links"),
+            HintGroup::Images => f.write_str("images"),
+            HintGroup::Inputs => f.write_str("inputs"),
+            HintGroup::Url => f.write_str("url"),
+            HintGroup::Custom(group) => f.write_str(&escape_arg(group)),
+        }
+    }
+}
+
+/// What to do with the element selected via hints.
+pub enum HintTarget {
+    /// Open the link in the current tab.
+    Normal,
+    /// Open the link in the current tab, even if it would open in a new tab.
+    Current,
+    /// Open the link in a new tab.
+    Tab,
+    /// Open the link in a new foreground tab.
+    TabFg,
+    /// Open the link in a new background tab.
+    TabBg,
+    /// Open the link in a new window.
+    Window,
+    Hover,
+    RightClick,
+    /// Yank the link to the clipboard.
+    Yank,
+    /// Yank the link to the primary selection.
+    YankPrimary,
+    /// Run `:{link}` as a command, with the link prefilled in the command line.
+    Run,
+    /// Prefill the command line with `:open {link}`.
+    Fill,
+    Download,
+    Delete,
+    /// Run a userscript with the given name and arguments.
+    Userscript(Vec<String>),
+    /// Spawn a program with the given arguments.
+    ///
+    /// qutebrowser joins the arguments with spaces before running `spawn`, which splits
+    /// them again, so an argument containing spaces becomes several; see [`hint_run`]
+    /// for passing a whole command line instead.
+    ///
+    /// [`hint_run`]: ./fn.hint_run.html
+    Spawn(Vec<String>),
+}
+
+impl fmt::Display for HintTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, args) = match self {
+            HintTarget::Normal => ("normal", None),
+            HintTarget::Current => ("current", None),
+            HintTarget::Tab => ("tab", None),
+            HintTarget::TabFg => ("tab-fg", None),
+            HintTarget::TabBg => ("tab-bg", None),
+            HintTarget::Window => ("window", None),
+            HintTarget::Hover => ("hover", None),
+            HintTarget::RightClick => ("right-click", None),
+            HintTarget::Yank => ("yank", None),
+            HintTarget::YankPrimary => ("yank-primary", None),
+            HintTarget::Run => ("run", None),
+            HintTarget::Fill => ("fill", None),
+            HintTarget::Download => ("download", None),
+            HintTarget::Delete => ("delete", None),
+            HintTarget::Userscript(args) => ("userscript", Some(args)),
+            HintTarget::Spawn(args) => ("spawn", Some(args)),
+        };
+
+        f.write_str(name)?;
+        for arg in args.into_iter().flatten() {
+            write!(f, " {}", escape_arg(arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of hint labels to show.
+#[derive(Clone, Copy, Debug)]
+pub enum HintKeyMode {
+    Number,
+    Letter,
+    Word,
+}
+
+impl HintKeyMode {
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HintKeyMode::Number => "number",
+            HintKeyMode::Letter => "letter",
+            HintKeyMode::Word => "word",
+        }
+    }
+}
+
+/// Builder for the `hint` command.
+pub struct Hint {
+    group: HintGroup,
+    target: HintTarget,
+    mode: Option<HintKeyMode>,
+}
+
+impl Hint {
+    #[inline]
+    pub fn new(group: HintGroup, target: HintTarget) -> Self {
+        Hint {
+            group,
+            target,
+            mode: None,
+        }
+    }
+
+    /// Use the given kind of hint labels instead of the one set in `hints.mode`
+    /// (`--mode {mode}`).
+    #[inline]
+    pub fn mode(mut self, mode: HintKeyMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Returns the `hint` command with the configured options.
+    pub fn command(&self) -> String {
+        let mut cmd = String::from("hint");
+        if let Some(mode) = self.mode {
+            cmd.push_str(" --mode ");
+            cmd.push_str(mode.as_str());
+        }
+        format!("{} {} {}", cmd, self.group, self.target)
+    }
+
+    /// Sends the `hint` command to qutebrowser.
+    #[inline]
+    pub fn send(&self) -> Result<(), io::Error> {
+        send_command(&self.command())
+    }
+}
+
+/// Starts hinting elements of `group`, spawning the command line `cmd` on the selected
+/// one (`hint {group} spawn {cmd}`).
+///
+/// `cmd` is split into the program and its arguments like a shell would, so quotes in it
+/// group arguments, e.g. `mpv --title "Hinted video" {hint-url}`. It is quoted as a single
+/// argument to `hint`, which qutebrowser passes on to `spawn` as is. `{hint-url}` in
+/// `cmd` is replaced with the URL of the selected element. To run a userscript instead,
+/// use [`Hint`] with [`HintTarget::Userscript`].
+///
+/// [`Hint`]: ./struct.Hint.html
+/// [`HintTarget::Userscript`]: ./enum.HintTarget.html#variant.Userscript
+#[inline]
+pub fn hint_run(group: HintGroup, cmd: &str) -> Result<(), io::Error> {
+    Hint::new(group, HintTarget::Spawn(vec![cmd.into()])).send()
+}
+
+/// Builder for the `open` command.
+#[derive(Clone, Debug, Default)]
+pub struct Open {
+    tab: bool,
+    bg: bool,
+    window: bool,
+    private: bool,
+    related: bool,
+    count: Option<u32>,
+}
+
+impl Open {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open in a new tab (`-t`).
+    #[inline]
+    pub fn tab(mut self, tab: bool) -> Self {
+        self.tab = tab;
+        self
+    }
+
+    /// Open in a new background tab (`-b`).
+    #[inline]
+    pub fn bg(mut self, bg: bool) -> Self {
+        self.bg = bg;
+        self
+    }
+
+    /// Open in a new window (`-w`).
+    #[inline]
+    pub fn window(mut self, window: bool) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Open in a new private window (`-p`).
+    #[inline]
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Position a new tab as related to the current one, as if a link on the page had
+    /// been followed (`-r`).
+    #[inline]
+    pub fn related(mut self, related: bool) -> Self {
+        self.related = related;
+        self
+    }
+
+    /// Open in the tab at the 1-based index `count` instead of the current tab, e.g. with
+    /// the count the userscript was run with (see [`CommandVars::count_parsed`]).
+    ///
+    /// `open` doesn't take the count as a flag, so it is passed as a prefix to the command
+    /// name. qutebrowser ignores it when opening in a new tab or window, and does nothing
+    /// if there is no tab at that index. `open` has accepted a count since before
+    /// qutebrowser v1.0, so every version that supports the `FIFO` commands used by this
+    /// crate respects it.
+    ///
+    /// [`CommandVars::count_parsed`]: ../env/struct.CommandVars.html#method.count_parsed
+    #[inline]
+    pub fn count(mut self, count: Option<u32>) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Returns the `open` command for `url` with the configured flags.
+    ///
+    /// If `url` is empty, it is omitted, and qutebrowser opens the default page instead.
+    /// `open` takes the rest of the line as the URL, so it isn't quoted, but newlines in it
+    /// are replaced with spaces and `;;` is broken up.
+    pub fn command(&self, url: &str) -> String {
+        let mut cmd = match self.count {
+            Some(count) => format!("{}open", count),
+            None => String::from("open"),
+        };
+        for (set, flag) in &[
+            (self.tab, " -t"),
+            (self.bg, " -b"),
+            (self.window, " -w"),
+            (self.private, " -p"),
+            (self.related, " -r"),
+        ] {
+            if *set {
+                cmd.push_str(flag);
+            }
+        }
+        if !url.is_empty() {
+            cmd.push(' ');
+            cmd.push_str(&escape_rest(url));
+        }
+        cmd
+    }
+
+    /// Sends the `open` command for `url` to qutebrowser.
+    #[inline]
+    pub fn send(&self, url: &str) -> Result<(), io::Error> {
+        send_command(&self.command(url))
+    }
+}
+
+/// Focuses the tab with `url` open if there is one, or opens `url` with `opts` otherwise.
+///
+/// Open tabs are read from the session qutebrowser last autosaved (see
+/// [`session::load_autosave`]), so tabs opened in the last few seconds may be missed. If
+/// there is no autosaved session, `url` is opened.
+///
+/// A tab in the focused window is focused with `tab-focus {index}`. For a tab in another
+/// window, `tab-select {window}/{index}` is sent with the position of the window in the
+/// session as its id, which only matches qutebrowser's window id if no earlier window has
+/// been closed.
+///
+/// [`session::load_autosave`]: ../session/fn.load_autosave.html
+pub fn open_unique(url: &str, opts: &Open) -> Result<(), io::Error> {
+    let session = match session::load_autosave() {
+        Ok(session) => session,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return opts.send(url),
+        Err(err) => return Err(err),
+    };
+
+    match session
+        .tabs()
+        .into_iter()
+        .find(|tab_ref| tab_ref.tab.url == url)
+    {
+        Some(tab_ref) => tab_ref.focus(),
+        None => opts.send(url),
+    }
+}
+
+/// Focuses the tab roughly `perc` percent of the way through the tabs of the focused
+/// window, where 0 is the first tab and 100 is the last (`tab-focus {index}`).
+///
+/// The number of tabs is read from the session qutebrowser last autosaved (see
+/// [`session::load_autosave`]). An error of kind `InvalidInput` is returned if `perc` is
+/// greater than 100.
+///
+/// [`session::load_autosave`]: ../session/fn.load_autosave.html
+pub fn tab_focus_perc(perc: u8) -> Result<(), io::Error> {
+    if perc > 100 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("tab percentage {} is greater than 100", perc),
+        ));
+    }
+
+    let session = session::load_autosave()?;
+    let count = session
+        .active_window()
+        .map(|window| window.tabs.len())
+        .filter(|&count| count > 0)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no tabs in session"))?;
+
+    let offset = ((count - 1) * usize::from(perc) + 50) / 100;
+    let message = format!("tab-focus {}", offset + 1);
+    send_command(&message)
+}
+
+/// Focuses the next tab after the current one that isn't pinned, wrapping around at the
+/// end (`tab-focus {index}`).
+///
+/// The tabs and their pinned state are read from the session qutebrowser last autosaved
+/// (see [`session::load_autosave`]), so changes made in the last few seconds may not be
+/// taken into account. Nothing is sent if there is no other unpinned tab.
+///
+/// [`session::load_autosave`]: ../session/fn.load_autosave.html
+#[inline]
+pub fn tab_next_unpinned() -> Result<(), io::Error> {
+    tab_focus_unpinned(true)
+}
+
+/// Focuses the previous tab before the current one that isn't pinned, wrapping around at
+/// the start; see [`tab_next_unpinned`].
+///
+/// [`tab_next_unpinned`]: ./fn.tab_next_unpinned.html
+#[inline]
+pub fn tab_prev_unpinned() -> Result<(), io::Error> {
+    tab_focus_unpinned(false)
+}
+
+fn tab_focus_unpinned(forward: bool) -> Result<(), io::Error> {
+    let session = session::load_autosave()?;
+    let tabs = session
+        .active_window()
+        .map(|window| window.tabs.as_slice())
+        .unwrap_or_default();
+    let current = tabs
+        .iter()
+        .position(|tab| tab.active)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no active tab in session"))?;
+
+    let count = tabs.len();
+    let target = (1..count)
+        .map(|step| {
+            if forward {
+                (current + step) % count
+            } else {
+                (current + count - step) % count
+            }
+        })
+        .find(|&idx| !tabs[idx].pinned);
+    match target {
+        Some(idx) => send_command(&format!("tab-focus {}", idx + 1)),
+        None => Ok(()),
+    }
+}
+
+const TAB_RING_STATE: &str = "tab-ring";
+
+/// A ring of tab indices to cycle through with `tab-focus`, persisted in a state file
+/// under [`data_dir`].
+///
+/// Userscripts can't query which tab is focused, so the ring stores whatever indices the
+/// user provides, e.g. the count the userscript was run with (see
+/// [`CommandVars::count_or`]). The indices are not updated when tabs are opened, closed,
+/// or moved, so they may come to refer to different tabs.
+///
+/// [`data_dir`]: ../env/fn.data_dir.html
+/// [`CommandVars::count_or`]: ../env/struct.CommandVars.html#method.count_or
+#[derive(Clone, Debug, Default)]
+pub struct TabRing {
+    /// The indices in the ring, starting with the current one.
+    tabs: Vec<u32>,
+}
+
+impl TabRing {
+    /// Reads the saved ring, or returns an empty ring if none has been saved.
+    pub fn load() -> Result<TabRing, io::Error> {
+        let tabs = state::read(TAB_RING_STATE)?
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect();
+        Ok(TabRing { tabs })
+    }
+
+    /// Returns the indices in the ring, starting with the current one.
+    #[inline]
+    pub fn tabs(&self) -> &[u32] {
+        &self.tabs
+    }
+
+    /// Adds the tab `index` to the ring as the current tab, moving it if it is already in
+    /// the ring, and saves the ring.
+    pub fn push(&mut self, index: u32) -> Result<(), io::Error> {
+        self.tabs.retain(|&tab| tab != index);
+        self.tabs.insert(0, index);
+        self.save()
+    }
+
+    /// Removes the tab `index` from the ring, if present, and saves the ring.
+    pub fn remove(&mut self, index: u32) -> Result<(), io::Error> {
+        self.tabs.retain(|&tab| tab != index);
+        self.save()
+    }
+
+    /// Advances to the next tab in the ring, focusing it (`tab-focus {index}`) and saving
+    /// the ring, and returns its index, or `None` if the ring is empty.
+    pub fn cycle_next(&mut self) -> Result<Option<u32>, io::Error> {
+        if !self.tabs.is_empty() {
+            self.tabs.rotate_left(1);
+        }
+        self.focus_current()
+    }
+
+    /// Goes back to the previous tab in the ring; see [`cycle_next`].
+    ///
+    /// [`cycle_next`]: #method.cycle_next
+    pub fn cycle_prev(&mut self) -> Result<Option<u32>, io::Error> {
+        if !self.tabs.is_empty() {
+            self.tabs.rotate_right(1);
+        }
+        self.focus_current()
+    }
+
+    fn focus_current(&self) -> Result<Option<u32>, io::Error> {
+        let index = match self.tabs.first() {
+            Some(&index) => index,
+            None => return Ok(None),
+        };
+        self.save()?;
+        send_command(&format!("tab-focus {}", index))?;
+        Ok(Some(index))
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let contents: String = self.tabs.iter().map(|tab| format!("{}\n", tab)).collect();
+        state::write(TAB_RING_STATE, &contents)
+    }
+}
+
+/// Opens each of `urls` in a new background tab, then focuses the first of them.
+///
+/// This sends `open -b {url}` for each URL, followed by `tab-focus -{n}` where `n` is the
+/// number of URLs. The indices of the new tabs can't be known, so this relies on
+/// qutebrowser's default of appending unrelated tabs to the end of the tab bar
+/// (`tabs.new_position.unrelated = last`); if that setting has been changed, a different
+/// tab may be focused.
+pub fn open_many_focus_first(urls: &[&str]) -> Result<(), io::Error> {
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    let open = Open::new().bg(true);
+    for url in urls {
+        open.send(url)?;
+    }
+    let message = format!("tab-focus -{}", urls.len());
+    send_command(&message)
+}
+
+/// A URL of one of qutebrowser's internal `qute://` pages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuteUrl(String);
+
+impl QuteUrl {
+    /// `qute://settings`
+    #[inline]
+    pub fn settings() -> Self {
+        QuteUrl("qute://settings".into())
+    }
+
+    /// `qute://help/{topic}`, e.g. `qute://help/commands.html#open`.
+    ///
+    /// The path of `topic` is percent-encoded; a `#fragment` is kept as such.
+    pub fn help(topic: &str) -> Self {
+        let (path, fragment) = match topic.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (topic, None),
+        };
+
+        let mut url = format!("qute://help/{}", encode_path(path));
+        if let Some(fragment) = fragment {
+            url.push('#');
+            url.push_str(&encode_path(fragment));
+        }
+        QuteUrl(url)
+    }
+
+    /// `qute://history`
+    #[inline]
+    pub fn history() -> Self {
+        QuteUrl("qute://history".into())
+    }
+
+    /// `qute://bookmarks`
+    #[inline]
+    pub fn bookmarks() -> Self {
+        QuteUrl("qute://bookmarks".into())
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for QuteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<QuteUrl> for String {
+    #[inline]
+    fn from(url: QuteUrl) -> String {
+        url.0
+    }
+}
+
+/// Percent-encodes everything in a URL path except unreserved characters and `/`.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(char::from(byte))
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Searches the web for the selected text, opening the search in a new tab if `tab` is
+/// `true`.
+///
+/// `engine_url` is a URL template in the format of qutebrowser's `url.searchengines`
+/// setting, with `{}` replaced by the percent-encoded selection, e.g.
+/// `https://duckduckgo.com/?q={}`. If there is no `{}`, the selection is appended.
+///
+/// If no text is selected, a warning message is shown instead and nothing is opened.
+#[cfg(feature = "percent-encoding")]
+pub fn search_selection(engine_url: &str, tab: bool) -> Result<(), io::Error> {
+    let selection = env::selected_text().unwrap_or_default();
+    if selection.trim().is_empty() {
+        return message_warning("No text selected to search for");
+    }
+
+    let query = urlencode(&selection);
+    let url = if engine_url.contains("{}") {
+        engine_url.replace("{}", &query)
+    } else {
+        format!("{}{}", engine_url, query)
+    };
+    Open::new().tab(tab).send(&url)
+}
+
+/// Searches for `query` with the search engine `engine` from the user's
+/// `url.searchengines` setting (`open {engine} {query}`), with `opts` for where to open
+/// the results.
+///
+/// qutebrowser resolves the engine by name, so this respects whatever URL the user has
+/// configured for it. If `engine` is not configured, qutebrowser searches for both words
+/// with the `DEFAULT` engine instead. Newlines in `query` are replaced with spaces.
+pub fn open_search(engine: &str, query: &str, opts: &Open) -> Result<(), io::Error> {
+    opts.send(&format!("{} {}", engine, escape_rest(query)))
+}
+
+/// The maximum length of a URL Chromium, and so QtWebEngine, accepts.
+const MAX_URL_LEN: usize = 2 * 1024 * 1024;
+
+/// Opens `bytes` as a `data:{mime};base64,{bytes}` URL, in a new tab if `tab` is `true`,
+/// e.g. to show a report generated by the userscript without writing it to a file.
+///
+/// QtWebEngine rejects URLs longer than 2 MiB, so if the URL would be longer, an error of
+/// kind `InvalidInput` is returned without opening it; write larger content to a file
+/// and open that instead.
+pub fn open_data(mime: &str, bytes: &[u8], tab: bool) -> Result<(), io::Error> {
+    let url = format!("data:{};base64,{}", mime, base64(bytes));
+    if url.len() > MAX_URL_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("data URL of {} bytes is too long", url.len()),
+        ));
+    }
+    Open::new().tab(tab).send(&url)
+}
+
+/// Encodes bytes with the standard base64 alphabet, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Opens the current page in the system's default browser, e.g. to hand off a page that
+/// doesn't work in qutebrowser.
+///
+/// The URL is passed to `xdg-open` on Unix, `open` on macOS, and
+/// `rundll32 url.dll,FileProtocolHandler` on Windows, which open it with the default
+/// handler for its scheme; if qutebrowser is the default browser, it is opened in
+/// qutebrowser again. No shell is involved, so characters like `&` in the URL are passed
+/// through as is. An error of kind `NotFound` is returned if there is no current URL, and
+/// an error of kind `Other` if the launcher fails.
+pub fn open_in_external_browser() -> Result<(), io::Error> {
+    let url =
+        env::url_opt().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no URL to open"))?;
+
+    let status = launcher_command(&url).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "failed to open {} in an external browser ({})",
+            url, status
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the command that opens `url` with the platform's default handler.
+fn launcher_command(url: &str) -> process::Command {
+    let mut cmd = if cfg!(windows) {
+        // Unlike `cmd /C start`, this doesn't interpret `&` and `|` in the URL.
+        let mut cmd = process::Command::new("rundll32");
+        cmd.arg("url.dll,FileProtocolHandler");
+        cmd
+    } else if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else {
+        process::Command::new("xdg-open")
+    };
+    cmd.arg(url);
+    cmd
+}
+
+/// Opens `url` as related to the current tab (`open [-t] -r {url}`).
+///
+/// If `tab` is `true`, `url` is opened in a new tab positioned next to the current one
+/// as qutebrowser does for followed links; otherwise, it is opened in the current tab.
+#[inline]
+pub fn open_related(url: &str, tab: bool) -> Result<(), io::Error> {
+    Open::new().tab(tab).related(true).send(url)
+}
+
+/// Opens `url` in a new private window (`open -p {url}`).
+#[inline]
+pub fn open_private(url: &str) -> Result<(), io::Error> {
+    Open::new().private(true).send(url)
+}
+
+/// Opens the default page in a new private window (`open -p`).
+#[inline]
+pub fn new_private_window() -> Result<(), io::Error> {
+    Open::new().private(true).send("")
+}
+
+/// Where to go with the `navigate` command.
+pub enum Navigate {
+    /// The previous page, as linked with `rel=prev` or a link matching
+    /// `hints.prev_regexes`.
+    Prev,
+    /// The next page, as linked with `rel=next` or a link matching `hints.next_regexes`.
+    Next,
+    /// The parent directory of the URL.
+    Up,
+    /// The URL with its last number incremented.
+    Increment,
+    /// The URL with its last number decremented.
+    Decrement,
+    /// The URL without its query and fragment.
+    Strip,
+}
+
+impl fmt::Display for Navigate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Navigate::Prev => "prev",
+            Navigate::Next => "next",
+            Navigate::Up => "up",
+            Navigate::Increment => "increment",
+            Navigate::Decrement => "decrement",
+            Navigate::Strip => "strip",
+        })
+    }
+}
+
+/// Sends the command `[{count}]navigate {where_}` to qutebrowser.
+///
+/// With `Increment` and `Decrement`, `count` is the amount to change the number by, so
+/// `navigate(Navigate::Increment, Some(5))` goes forward five pages of a numbered gallery;
+/// with `Up`, it is the number of directories to go up. Which parts of the URL are
+/// searched for a number is controlled by the `url.incdec_segments` setting and can't be
+/// changed per invocation. `navigate` doesn't take the count as a flag, so it is passed as
+/// a prefix to the command name instead.
+pub fn navigate(where_: Navigate, count: Option<u32>) -> Result<(), io::Error> {
+    let message = match count {
+        Some(count) => format!("{}navigate {}", count, where_),
+        None => format!("navigate {}", where_),
+    };
+    send_command(&message)
+}
+
+/// Sends the command `tab-give [{win_id}]` to qutebrowser to give the current tab to the
+/// window with the given id, or to a new window if `win_id` is `None`.
+pub fn tab_give(win_id: Option<u32>) -> Result<(), io::Error> {
+    let message = match win_id {
+        Some(id) => format!("tab-give {}", id),
+        None => "tab-give".into(),
+    };
+    send_command(&message)
+}
+
+/// Moves the current tab into a new window (`tab-give`).
+#[inline]
+pub fn move_tab_to_new_window() -> Result<(), io::Error> {
+    tab_give(None)
+}
+
+/// Searches the page for `term` without moving the viewport.
+///
+/// qutebrowser has no flag to search without scrolling to the first match. Instead,
+/// this sends `search {term} ;; jump-mark "'"`: qutebrowser sets the `'` mark to the
+/// current position before jumping to a match, so jumping back to it restores the
+/// original position while leaving the matches highlighted. Newlines in `term` are
+/// replaced with spaces and `;;` is broken up, so it can't end the chain early.
+pub fn search_highlight(term: &str) -> Result<(), io::Error> {
+    let message = format!(
+        "search {} ;; jump-mark {}",
+        escape_rest(term),
+        escape_arg("'")
+    );
+    send_command(&message)
+}
+
+/// Shows a print preview of the current page (`print --preview`).
+///
+/// Unlike [`save_as_pdf`], this is interactive: the user sees the preview dialog and
+/// chooses where and how to print from there.
+///
+/// [`save_as_pdf`]: ./fn.save_as_pdf.html
+pub fn preview_print() -> Result<(), io::Error> {
+    send_command("print --preview")
+}
+
+/// Saves the current page as a PDF file at `path` without any dialog
+/// (`print --pdf {path}`).
+pub fn save_as_pdf<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let message = format!(
+        "print --pdf {}",
+        escape_arg(&path.as_ref().to_string_lossy())
+    );
+    send_command(&message)
+}
+
+/// Opens the current page in reader mode (`spawn --userscript readability`).
+///
+/// qutebrowser has no built-in reader mode. This runs the `readability` userscript
+/// shipped with qutebrowser, which must be installed in one of the userscript
+/// directories; it renders a simplified version of the page and opens it in a new tab.
+#[inline]
+pub fn reader_mode() -> Result<(), io::Error> {
+    spawn_userscript("readability", &[])
+}
+
+/// Runs the userscript `name` with `args` (`spawn --userscript {name} {args...}`).
+///
+/// `name` is looked up in qutebrowser's userscript directories unless it is a path. Each
+/// of `args` is quoted as needed.
+pub fn spawn_userscript(name: &str, args: &[&str]) -> Result<(), io::Error> {
+    let mut message = format!("spawn --userscript {}", escape_arg(name));
+    for arg in args {
+        message.push(' ');
+        message.push_str(&escape_arg(arg));
+    }
+    send_command(&message)
+}
+
+const SCROLL_SESSION: &str = "_qutescript_scroll";
+const SCROLL_SESSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Saves the scroll position of the current page, to be restored later with
+/// [`restore_scroll_position`].
+///
+/// The position can't be read back via `jseval` (see [`Fifo`]). Instead, qutebrowser is
+/// made to save the focused window to a temporary session (see
+/// [`session::save`]), and the position is read from the focused tab's entry in it.
+/// The position is stored in a state file under [`data_dir`] keyed by the URL of the
+/// page, so positions saved for different pages don't overwrite each other.
+///
+/// [`Fifo`]: ../env/struct.Fifo.html
+/// [`restore_scroll_position`]: ./fn.restore_scroll_position.html
+/// [`session::save`]: ../session/fn.save.html
+/// [`data_dir`]: ../env/fn.data_dir.html
+pub fn save_scroll_position() -> Result<(), io::Error> {
+    let saved = session::save(SCROLL_SESSION, SCROLL_SESSION_TIMEOUT);
+    let _ = fs::remove_file(session::path(SCROLL_SESSION));
+
+    let (x, y) = saved?
+        .active_tab()
+        .map(|tab| tab.scroll_pos)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no active tab in session"))?;
+    state::write(&scroll_state_name(), &format!("{} {}", x, y))
+}
+
+/// Restores the scroll position of the current page saved with
+/// [`save_scroll_position`].
+///
+/// This sends `scroll-to-perc 0 ;; scroll-to-perc --horizontal 0 ;; scroll-px {x} {y}`.
+/// Nothing is sent if no position was saved for the page.
+///
+/// [`save_scroll_position`]: ./fn.save_scroll_position.html
+pub fn restore_scroll_position() -> Result<(), io::Error> {
+    let saved = match state::read(&scroll_state_name())? {
+        Some(saved) => saved,
+        None => return Ok(()),
+    };
+
+    let mut coords = saved.split_whitespace().map(str::parse::<i64>);
+    let (x, y) = match (coords.next(), coords.next()) {
+        (Some(Ok(x)), Some(Ok(y))) => (x, y),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid saved scroll position",
+            ))
+        }
+    };
+
+    let message = format!(
+        "scroll-to-perc 0 ;; scroll-to-perc --horizontal 0 ;; scroll-px {} {}",
+        x, y
+    );
+    send_command(&message)
+}
+
+#[inline]
+fn scroll_state_name() -> String {
+    format!("scroll-{}", env::url_cache_key())
+}
+
+/// Sends each line read from `reader` to qutebrowser as a command, until EOF.
+///
+/// Leading whitespace and `:` are stripped from each line, so commands can be written as
+/// they would be typed in qutebrowser's command line. Blank lines are skipped.
+pub fn pipe_commands<R: BufRead>(reader: R) -> Result<(), io::Error> {
+    for line in reader.lines() {
+        let line = line?;
+        let cmd = line.trim_start().trim_start_matches(':').trim();
+        if !cmd.is_empty() {
+            send_command(cmd)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends each line read from stdin to qutebrowser as a command, until EOF.
+///
+/// See [`pipe_commands`] for how lines are handled.
+///
+/// [`pipe_commands`]: ./fn.pipe_commands.html
+#[inline]
+pub fn pipe_stdin_to_fifo() -> Result<(), io::Error> {
+    pipe_commands(io::stdin().lock())
+}
+
+/// Quotes a string for use as a single argument in a qutebrowser command.
+///
+/// The string is returned unchanged if it needs no quoting. Otherwise, it is wrapped in
+/// double quotes with any backslashes and double quotes escaped.
+///
+/// Quoting can't protect against everything: qutebrowser ends a command at a newline and
+/// splits commands on `;;` before parsing arguments. Newlines are therefore replaced with
+/// spaces and `;;` is broken up into `; ;`, so the argument may not arrive exactly as
+/// given if it contains either.
+///
+/// Note that commands which take the rest of the line as their last argument (e.g.
+/// `open` or `message-info`) receive that text verbatim, quotes included, so it should
+/// not be passed through this function.
+pub fn escape_arg(s: &str) -> String {
+    let s = &escape_rest(s);
+    let needs_quotes = s.is_empty()
+        || s.chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    if !needs_quotes {
+        return s.into();
+    }
+
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Makes text safe to pass as the verbatim last argument of a command (e.g. `yank
+/// inline`): newlines, which would end the command, are replaced with spaces, and `;;`,
+/// which would start a new one, is broken up by putting a space between every pair of
+/// adjacent `;`.
+pub(crate) fn escape_rest(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' | '\r' => escaped.push(' '),
+            ';' => {
+                if escaped.ends_with(';') {
+                    escaped.push(' ');
+                }
+                escaped.push(';');
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quotes a string as a single-quoted JavaScript string literal.
+///
+/// Quotes, backslashes, and line terminators are escaped, so the literal always fits on
+/// a single line. This is separate from [`escape_arg`]: JavaScript passed to `jseval` is
+/// taken verbatim by qutebrowser, so only this escaping applies to it.
+///
+/// [`escape_arg`]: ./fn.escape_arg.html
+pub fn escape_js(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => escaped.push_str("\\'"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('\'');
+    escaped
+}
+
+/// Evaluates JavaScript in the current page without showing the result
+/// (`jseval -q {code}`).
+///
+/// `code` is sent as a single command, so it must not contain newlines.
+pub fn jseval(code: &str) -> Result<(), io::Error> {
+    let message = format!("jseval -q {}", code);
+    send_command(&message)
+}
+
+/// The JavaScript world to run `jseval` code in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsWorld {
+    /// The world of the page's own scripts.
+    Main,
+    /// The world used by qutebrowser itself.
+    Application,
+    /// The world used by greasemonkey scripts.
+    User,
+    /// The world `jseval` runs in by default.
+    Jseval,
+    /// A world given by its id; see [`JsWorld::numbered`].
+    ///
+    /// [`JsWorld::numbered`]: ./enum.JsWorld.html#method.numbered
+    Numbered(JsWorldId),
+}
+
+/// A validated JavaScript world id, between 0 and [`MAX_JS_WORLD`].
+///
+/// [`MAX_JS_WORLD`]: ./constant.MAX_JS_WORLD.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JsWorldId(u16);
+
+impl JsWorldId {
+    #[inline]
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+/// The largest JavaScript world id accepted by qutebrowser.
+pub const MAX_JS_WORLD: u16 = 256;
+
+impl JsWorld {
+    /// Returns the world with id `n`, or an error if `n` is greater than
+    /// [`MAX_JS_WORLD`], which qutebrowser would reject.
+    ///
+    /// [`MAX_JS_WORLD`]: ./constant.MAX_JS_WORLD.html
+    #[inline]
+    pub fn numbered(n: u16) -> Result<JsWorld, InvalidJsWorld> {
+        if n > MAX_JS_WORLD {
+            return Err(InvalidJsWorld(n));
+        }
+        Ok(JsWorld::Numbered(JsWorldId(n)))
+    }
+}
+
+impl fmt::Display for JsWorld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsWorld::Main => f.write_str("main"),
+            JsWorld::Application => f.write_str("application"),
+            JsWorld::User => f.write_str("user"),
+            JsWorld::Jseval => f.write_str("jseval"),
+            JsWorld::Numbered(id) => write!(f, "{}", id.get()),
+        }
+    }
+}
+
+/// Error returned when a JavaScript world id is out of range.
+#[derive(Clone, Copy, Debug)]
+pub struct InvalidJsWorld(pub u16);
+
+impl fmt::Display for InvalidJsWorld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "JavaScript world {} is out of range 0-{}",
+            self.0, MAX_JS_WORLD
+        )
+    }
+}
+
+impl Error for InvalidJsWorld {}
+
+/// Evaluates JavaScript in the given world of the current page without showing the
+/// result (`jseval -q --world {world} {code}`).
+///
+/// `code` is sent as a single command, so it must not contain newlines.
+pub fn jseval_in(code: &str, world: JsWorld) -> Result<(), io::Error> {
+    let message = format!("jseval -q --world {} {}", world, code);
+    send_command(&message)
+}
+
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Evaluates the JavaScript expression `js_expr` in the current page and returns its
+/// result converted to a string.
+///
+/// The result can't be returned through the `FIFO` (see [`Fifo`]). Instead, `js_expr` is
+/// wrapped in JavaScript that makes the page download the result as a file
+/// with a unique name, which is then read from [`download_dir`] (see
+/// [`download::wait_for`]) and removed. A promise is awaited, and if evaluating the
+/// expression throws, the result is `Error: ` followed by the error.
+///
+/// A download is the only way for page JavaScript to get data out of the browser: it has
+/// no access to the filesystem, so it can't write to a named pipe created by the
+/// userscript, and downloads can't be directed to one either, since QtWebEngine writes
+/// them to a temporary file before moving them into place.
+///
+/// This has some limitations:
+///
+/// - The download must not prompt for a location, so `downloads.location.prompt` must be
+///   `false`, and `downloads.location.directory` must match `QUTE_DOWNLOAD_DIR`.
+/// - The download shows up in qutebrowser's download list.
+/// - The page may block downloads or not finish within 5 seconds, in which case an error
+///   of kind `TimedOut` is returned.
+/// - It fails immediately where the `FIFO` isn't a named pipe (see [`fifo_is_pipe`]).
+///
+/// `js_expr` must not contain newlines.
+///
+/// [`Fifo`]: ../env/struct.Fifo.html
+/// [`fifo_is_pipe`]: ../env/fn.fifo_is_pipe.html
+/// [`download_dir`]: ../env/fn.download_dir.html
+/// [`download::wait_for`]: ../download/fn.wait_for.html
+pub fn jseval_capture(js_expr: &str) -> Result<String, io::Error> {
+    if !env::fifo_is_pipe() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "results can't be captured while the userscript is running on this platform",
+        ));
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or(0);
+    let filename = format!("qutescript-capture-{}-{}.txt", std::process::id(), nanos);
+
+    jseval(&capture_script(js_expr, &filename))?;
+    let path = download::wait_for(&filename, CAPTURE_TIMEOUT)?;
+    let result = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Returns JavaScript that evaluates `js_expr` and downloads its result as `filename`.
+fn capture_script(js_expr: &str, filename: &str) -> String {
+    format!(
+        "(function() {{ \
+         Promise.resolve().then(function() {{ return ({expr}); }}) \
+         .then(function(v) {{ return String(v); }}, function(e) {{ return 'Error: ' + e; }}) \
+         .then(function(s) {{ \
+         var a = document.createElement('a'); \
+         a.href = URL.createObjectURL(new Blob([s], {{ type: 'text/plain' }})); \
+         a.download = {filename}; \
+         document.body.appendChild(a); a.click(); a.remove(); \
+         }}); \
+         }})()",
+        expr = js_expr,
+        filename = escape_js(filename)
+    )
+}
+
+/// Sets the title of the current page via `jseval`.
+#[inline]
+pub fn set_title(title: &str) -> Result<(), io::Error> {
+    jseval(&format!("document.title = {}", escape_js(title)))
+}
+
+/// Percent-encodes a string for use as a URL component, e.g. a search query.
+///
+/// All characters except ASCII alphanumerics are encoded.
+#[cfg(feature = "percent-encoding")]
+#[inline]
+pub fn urlencode(s: &str) -> String {
+    percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
+}
+
+/// Decodes a percent-encoded string, failing if the decoded bytes are not valid UTF-8.
+///
+/// `+` is not treated as an encoded space.
+#[cfg(feature = "percent-encoding")]
+#[inline]
+pub fn urldecode(s: &str) -> Result<String, std::str::Utf8Error> {
+    percent_encoding::percent_decode_str(s)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+}
+
+/// A link on the current page, as returned by [`extract_links`].
+///
+/// [`extract_links`]: ./fn.extract_links.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// The target of the link.
+    ///
+    /// With the `url` feature, relative links are resolved against the URL of the page.
+    /// Otherwise, this is the `href` attribute as written.
+    pub href: String,
+    /// The text of the link, with tags removed and whitespace collapsed.
+    pub text: String,
+}
+
+/// Returns the links (`<a href>` elements) on the current page, in document order.
+///
+/// The links are extracted from the HTML source given by [`html_contents_lossy`] with a
+/// simple scanner rather than a full HTML parser, so links added by scripts or in
+/// malformed markup may be missed. `<base>` elements are not taken into account when
+/// resolving relative links.
+///
+/// [`html_contents_lossy`]: ../env/fn.html_contents_lossy.html
+pub fn extract_links() -> Result<Vec<Link>, io::Error> {
+    let html = env::html_contents_lossy()?;
+
+    #[cfg(feature = "url")]
+    let base = env::url_opt().and_then(|url| url::Url::parse(&url).ok());
+
+    Ok(crate::html::links(&html)
+        .into_iter()
+        .map(|(href, text)| {
+            #[cfg(feature = "url")]
+            let href = match base.as_ref().and_then(|base| base.join(&href).ok()) {
+                Some(resolved) => resolved.into(),
+                None => href,
+            };
+            Link { href, text }
+        })
+        .collect())
+}
+
+/// Returns the canonical URL of the current page, as given by a
+/// `<link rel="canonical" href="...">` element, or `None` if there is none.
+///
+/// The HTML source is scanned as in [`extract_links`]. With the `url` feature, a relative
+/// URL is resolved against the URL of the page; otherwise, it is returned as written.
+///
+/// [`extract_links`]: ./fn.extract_links.html
+pub fn canonical_url() -> Result<Option<String>, io::Error> {
+    let html = env::html_contents_lossy()?;
+    let href = crate::html::canonical(&html);
+
+    #[cfg(feature = "url")]
+    let href = href.map(|href| {
+        env::url_opt()
+            .and_then(|url| url::Url::parse(&url).ok())
+            .and_then(|base| base.join(&href).ok())
+            .map_or(href, String::from)
+    });
+
+    Ok(href)
+}
+
+/// A record of the commands sent to qutebrowser, e.g. for debugging or replaying them
+/// later.
+///
+/// Recording is enabled per thread with [`start_command_log`], after which every command
+/// sent through [`send_command`] (and so every helper in this crate) is recorded until
+/// [`take_command_log`] is called.
+///
+/// [`start_command_log`]: ./fn.start_command_log.html
+/// [`send_command`]: ./fn.send_command.html
+/// [`take_command_log`]: ./fn.take_command_log.html
+#[derive(Clone, Debug, Default)]
+pub struct CommandLog {
+    commands: Vec<String>,
+}
+
+impl CommandLog {
+    /// Returns the recorded commands, in the order they were sent.
+    #[inline]
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    /// Returns the recorded commands as a script with one command per line, which can be
+    /// replayed by writing it to the `FIFO`.
+    pub fn to_script(&self) -> String {
+        let mut script = String::new();
+        for cmd in &self.commands {
+            script.push_str(cmd);
+            script.push('\n');
+        }
+        script
+    }
+}
+
+thread_local! {
+    static COMMAND_LOG: RefCell<Option<CommandLog>> = const { RefCell::new(None) };
+}
+
+/// Starts recording the commands sent on this thread into a new [`CommandLog`],
+/// discarding any commands recorded so far.
+///
+/// [`CommandLog`]: ./struct.CommandLog.html
+#[inline]
+pub fn start_command_log() {
+    COMMAND_LOG.with(|log| *log.borrow_mut() = Some(CommandLog::default()));
+}
+
+/// Stops recording the commands sent on this thread, returning those recorded since
+/// [`start_command_log`] was called, or `None` if it wasn't.
+///
+/// [`start_command_log`]: ./fn.start_command_log.html
+#[inline]
+pub fn take_command_log() -> Option<CommandLog> {
+    COMMAND_LOG.with(|log| log.borrow_mut().take())
+}
+
+thread_local! {
+    static BATCH: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Starts batching the commands sent on this thread.
+///
+/// Until [`flush_batch`] is called, commands sent through [`send_command`] (and so every
+/// helper in this crate) are collected in a buffer instead of being written to the
+/// `FIFO`. Calling this while already batching has no effect.
+///
+/// Helpers that wait for qutebrowser to act on a command, such as [`session::save`],
+/// time out while batching, since the command isn't written until the batch is flushed.
+///
+/// [`flush_batch`]: ./fn.flush_batch.html
+/// [`send_command`]: ./fn.send_command.html
+/// [`session::save`]: ../session/fn.save.html
+#[inline]
+pub fn begin_batch() {
+    BATCH.with(|batch| {
+        batch.borrow_mut().get_or_insert_with(Vec::new);
+    });
+}
+
+/// Returns whether commands sent on this thread are being batched, i.e. [`begin_batch`]
+/// has been called without a matching [`flush_batch`].
+///
+/// [`begin_batch`]: ./fn.begin_batch.html
+/// [`flush_batch`]: ./fn.flush_batch.html
+#[inline]
+pub fn is_batching() -> bool {
+    BATCH.with(|batch| batch.borrow().is_some())
+}
+
+/// Stops batching the commands sent on this thread, writing the commands collected since
+/// [`begin_batch`] to the `FIFO` in a single write, one per line.
+///
+/// Nothing is written if no commands were collected or [`begin_batch`] wasn't called.
+///
+/// [`begin_batch`]: ./fn.begin_batch.html
+pub fn flush_batch() -> Result<(), io::Error> {
+    let commands = match BATCH.with(|batch| batch.borrow_mut().take()) {
+        Some(commands) if !commands.is_empty() => commands,
+        _ => return Ok(()),
+    };
+
+    let mut message = commands.join("\n");
+    message.push('\n');
+    env::fifo().write(&message)
+}
+
+/// Stops batching without writing the collected commands.
+#[cfg(test)]
+pub(crate) fn discard_batch() {
+    BATCH.with(|batch| batch.borrow_mut().take());
+}
+
+static COMMAND_HISTORY: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+const COMMAND_HISTORY_FILE: &str = "command-history";
+
+/// Starts appending every command sent through [`send_command`] (and so every helper in
+/// this crate) to a history file at `path`, or at [`data_path`]`("command-history")` if
+/// `path` is `None`.
+///
+/// Each line of the file holds the time the command was sent, in seconds since the Unix
+/// epoch, and the command, separated by a tab. History is off by default. Commands are
+/// recorded once they have been written to the `FIFO` (or added to a batch), and failing
+/// to record one only prints a warning to stderr, so history never stops commands from
+/// being sent.
+///
+/// [`send_command`]: ./fn.send_command.html
+/// [`data_path`]: ../env/fn.data_path.html
+pub fn enable_command_history(path: Option<&Path>) {
+    let path = match path {
+        Some(path) => path.into(),
+        None => env::data_path(COMMAND_HISTORY_FILE),
+    };
+    *lock_command_history() = Some(path);
+}
+
+/// Stops appending sent commands to the history file.
+#[inline]
+pub fn disable_command_history() {
+    *lock_command_history() = None;
+}
+
+#[inline]
+fn lock_command_history() -> MutexGuard<'static, Option<PathBuf>> {
+    COMMAND_HISTORY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn append_command_history(path: &Path, cmd: &str) -> Result<(), io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}\t{}", timestamp, escape_rest(cmd))
+}
+
+/// Sends `cmd`, then runs `followup` after `delay_ms` milliseconds
+/// (`{cmd} ;; later {delay_ms} {followup}`), e.g. to scroll a page once it has loaded.
+///
+/// Both are written as a single line, so they are run in order even if other commands are
+/// written to the `FIFO` in between. `cmd` is sent as is and must not contain newlines.
+/// `followup` is taken as the rest of the `later` command, so newlines in it are replaced
+/// with spaces, and `;;` is broken up, since it would otherwise end the followup and run
+/// the rest immediately.
+pub fn send_then_later(cmd: &str, delay_ms: u32, followup: &str) -> Result<(), io::Error> {
+    let message = format!("{} ;; later {} {}", cmd, delay_ms, escape_rest(followup));
+    send_command(&message)
+}
+
+/// The default size in bytes above which [`send_command`] warns about a command; see
+/// [`set_command_size_warning`].
+///
+/// [`send_command`]: ./fn.send_command.html
+/// [`set_command_size_warning`]: ./fn.set_command_size_warning.html
+pub const DEFAULT_COMMAND_SIZE_WARNING: usize = 64 * 1024;
+
+static COMMAND_SIZE_WARNING: AtomicUsize = AtomicUsize::new(DEFAULT_COMMAND_SIZE_WARNING);
+
+/// Sets the size in bytes above which commands sent with [`send_command`] print a warning
+/// to stderr, or disables the warning if `threshold` is `None`.
+///
+/// Commands are still sent when they exceed the threshold. The default is
+/// [`DEFAULT_COMMAND_SIZE_WARNING`], the size of a pipe's buffer on Linux: larger commands
+/// block until qutebrowser has read part of them, and qutebrowser parses each command as
+/// a single line, which gets slow for huge `jseval` payloads. Long JavaScript is better
+/// written to a file and run with `jseval --file {path}`.
+///
+/// [`send_command`]: ./fn.send_command.html
+/// [`DEFAULT_COMMAND_SIZE_WARNING`]: ./constant.DEFAULT_COMMAND_SIZE_WARNING.html
+#[inline]
+pub fn set_command_size_warning(threshold: Option<usize>) {
+    COMMAND_SIZE_WARNING.store(threshold.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Returns the warning to print for `cmd` if it exceeds the size set with
+/// [`set_command_size_warning`].
+///
+/// [`set_command_size_warning`]: ./fn.set_command_size_warning.html
+fn command_size_warning(cmd: &str) -> Option<String> {
+    let threshold = COMMAND_SIZE_WARNING.load(Ordering::Relaxed);
+    if cmd.len() <= threshold {
+        return None;
+    }
+    Some(format!(
+        "qutescript: command of {} bytes exceeds {} bytes; \
+         consider writing JavaScript to a file and using jseval --file",
+        cmd.len(),
+        threshold
+    ))
+}
+
 pub fn send_command(cmd: &str) -> Result<(), io::Error> {
-    let fifo = env::fifo();
-    fifo.write(cmd)
+    if let Some(warning) = command_size_warning(cmd) {
+        eprintln!("{}", warning);
+    }
+
+    COMMAND_LOG.with(|log| {
+        if let Some(log) = log.borrow_mut().as_mut() {
+            log.commands.push(cmd.into());
+        }
+    });
+
+    let batched = BATCH.with(|batch| match batch.borrow_mut().as_mut() {
+        Some(commands) => {
+            commands.push(cmd.into());
+            true
+        }
+        None => false,
+    });
+    if !batched {
+        env::fifo().write(cmd)?;
+    }
+
+    if let Some(path) = lock_command_history().as_ref() {
+        if let Err(err) = append_command_history(path, cmd) {
+            eprintln!(
+                "qutescript: failed to record command in {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn validate_keys_accepts_both_modifier_separators() {
+        for keys in [
+            "<Ctrl-x>",
+            "<Num+5>",
+            "<AltGr-e>",
+            "<Ctrl+Shift-t>",
+            "<Ctrl-->",
+            "<Ctrl++>",
+        ] {
+            assert!(validate_keys(keys).is_ok(), "{}", keys);
+        }
+        assert!(validate_keys("<Foo+x>").is_err());
+        assert!(validate_keys("<Ctrl+>").is_err());
+    }
+
+    #[test]
+    fn launcher_command_passes_url_as_one_argument() {
+        let url = "https://example.com/?a=1&b=2|x";
+        let cmd = launcher_command(url);
+        let args: Vec<_> = cmd.get_args().collect();
+        if cfg!(windows) {
+            assert_eq!(cmd.get_program(), "rundll32");
+            assert_eq!(args, ["url.dll,FileProtocolHandler", url]);
+        } else if cfg!(target_os = "macos") {
+            assert_eq!(cmd.get_program(), "open");
+            assert_eq!(args, [url]);
+        } else {
+            assert_eq!(cmd.get_program(), "xdg-open");
+            assert_eq!(args, [url]);
+        }
+    }
+
+    #[test]
+    fn command_history_records_sent_commands() {
+        let _lock = testing::lock_env();
+        let dir = testing::temp_dir("command-history");
+        let path = dir.join("history");
+
+        enable_command_history(Some(&path));
+        let (ret, _) = testing::capture(|| send_command("message-info recorded"));
+        disable_command_history();
+        ret.unwrap();
+
+        let history = fs::read_to_string(&path).unwrap();
+        let line = history
+            .lines()
+            .find(|line| line.ends_with("\tmessage-info recorded"))
+            .unwrap();
+        let (timestamp, _) = line.split_once('\t').unwrap();
+        assert!(timestamp.parse::<u64>().unwrap() > 0);
+    }
+
+    #[test]
+    fn command_history_errors_dont_stop_commands() {
+        let _lock = testing::lock_env();
+        let dir = testing::temp_dir("command-history-error");
+
+        // A directory can't be opened for appending.
+        enable_command_history(Some(&dir));
+        let (ret, commands) = testing::capture(|| send_command("message-info sent"));
+        disable_command_history();
+        ret.unwrap();
+        assert_eq!(commands, ["message-info sent"]);
+    }
+
+    #[test]
+    fn yank_markdown_link_escapes_title_and_url() {
+        let _env = testing::set_env(&[
+            ("QUTE_URL", Some("https://example.com/a page (1)")),
+            ("QUTE_TITLE", Some("A *bold* [title]")),
+        ]);
+        let (ret, commands) = testing::capture(|| yank_markdown_link(false));
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            ["yank inline [A \\*bold\\* \\[title\\]](https://example.com/a%20page%20%281%29)"]
+        );
+    }
+
+    #[test]
+    fn yank_markdown_link_without_url() {
+        let _env = testing::set_env(&[("QUTE_URL", None), ("QUTE_TITLE", Some("title"))]);
+        let (ret, commands) = testing::capture(|| yank_markdown_link(false));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn report_on_error_shows_errors() {
+        let _env = testing::set_env(&[("QUTE_FIFO", Some("/nonexistent/fifo"))]);
+        let (ret, commands) = testing::capture(|| {
+            report_on_error(Err::<(), _>(io::Error::other("something failed")))
+        });
+        assert_eq!(ret.unwrap_err().to_string(), "something failed");
+        assert_eq!(commands, ["message-error something failed"]);
+
+        let (ret, commands) = testing::capture(|| report_on_error(Ok(1)));
+        assert_eq!(ret.unwrap(), 1);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn open_many_focus_first_focuses_first_new_tab() {
+        let (ret, commands) = testing::capture(|| open_many_focus_first(&["a", "b"]));
+        ret.unwrap();
+        assert_eq!(commands, ["open -b a", "open -b b", "tab-focus -2"]);
+
+        let (ret, commands) = testing::capture(|| open_many_focus_first(&[]));
+        ret.unwrap();
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn command_log_to_script() {
+        start_command_log();
+        begin_batch();
+        message_info("one").unwrap();
+        reader_mode().unwrap();
+        discard_batch();
+        let log = take_command_log().unwrap();
+        assert_eq!(
+            log.to_script(),
+            "message-info one\nspawn --userscript readability\n"
+        );
+        assert!(take_command_log().is_none());
+        assert_eq!(CommandLog::default().to_script(), "");
+    }
+
+    #[test]
+    fn command_size_warning_threshold() {
+        let _lock = testing::lock_env();
+        set_command_size_warning(Some(10));
+        let below = command_size_warning("0123456789");
+        let above = command_size_warning("0123456789a");
+        set_command_size_warning(None);
+        let disabled = command_size_warning(&"a".repeat(DEFAULT_COMMAND_SIZE_WARNING * 2));
+        set_command_size_warning(Some(DEFAULT_COMMAND_SIZE_WARNING));
+
+        assert_eq!(below, None);
+        assert!(above.unwrap().contains("11 bytes exceeds 10 bytes"));
+        assert_eq!(disabled, None);
+    }
+
+    #[test]
+    fn escape_js_quotes_backslashes_and_newlines() {
+        assert_eq!(escape_js("it's"), r"'it\'s'");
+        assert_eq!(escape_js(r"a\b"), r"'a\\b'");
+        assert_eq!(escape_js("a\nb\r\u{2028}"), r"'a\nb\r\u2028'");
+        assert_eq!(escape_js("\u{1}\t"), r"'\x01\x09'");
+        assert_eq!(escape_js("\"double\""), "'\"double\"'");
+    }
+
+    #[test]
+    fn base64_pads_partial_chunks() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe]), "//4=");
+    }
+
+    #[test]
+    fn open_data_sends_data_url() {
+        let (ret, commands) = testing::capture(|| open_data("text/plain", b"foob", true));
+        ret.unwrap();
+        assert_eq!(commands, ["open -t data:text/plain;base64,Zm9vYg=="]);
+
+        let too_long = vec![0; MAX_URL_LEN];
+        let (ret, commands) = testing::capture(|| open_data("text/plain", &too_long, false));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn capture_script_downloads_result() {
+        let script = capture_script("document.title", "it's.txt");
+        assert!(!script.contains('\n'));
+        assert!(script.starts_with("(function() { "));
+        assert!(script.contains("function() { return (document.title); }"));
+        assert!(script.contains(r"a.download = 'it\'s.txt';"));
+        assert!(script.ends_with("})()"));
+    }
+
+    #[test]
+    fn pipe_commands_sends_each_line() {
+        let input: &[u8] = b":open a\n\n   :scroll down  \nreload\r\n";
+        let (ret, commands) = testing::capture(|| pipe_commands(input));
+        ret.unwrap();
+        assert_eq!(commands, ["open a", "scroll down", "reload"]);
+    }
+
+    #[test]
+    fn tab_ring_push_and_cycle() {
+        let dir = testing::temp_dir("util-tab-ring");
+        let _env = testing::set_env(&[("QUTE_DATA_DIR", dir.to_str())]);
+
+        let mut ring = TabRing::load().unwrap();
+        assert!(ring.tabs().is_empty());
+        let (none, commands) = testing::capture(|| ring.cycle_next());
+        assert_eq!(none.unwrap(), None);
+        assert!(commands.is_empty());
+
+        for index in [3, 5, 7, 5] {
+            ring.push(index).unwrap();
+        }
+        assert_eq!(ring.tabs(), [5, 7, 3]);
+
+        let (focused, commands) = testing::capture(|| {
+            let next = ring.cycle_next()?;
+            let next_again = ring.cycle_next()?;
+            let prev = ring.cycle_prev()?;
+            Ok::<_, io::Error>((next, next_again, prev))
+        });
+        assert_eq!(focused.unwrap(), (Some(7), Some(3), Some(7)));
+        assert_eq!(commands, ["tab-focus 7", "tab-focus 3", "tab-focus 7"]);
+
+        ring.remove(3).unwrap();
+        assert_eq!(TabRing::load().unwrap().tabs(), [7, 5]);
+    }
+
+    #[test]
+    fn toggle_passthrough_alternates_modes() {
+        let dir = testing::temp_dir("util-toggle-passthrough");
+        let _env = testing::set_env(&[("QUTE_DATA_DIR", dir.to_str())]);
+
+        let (ret, first) = testing::capture(toggle_passthrough);
+        ret.unwrap();
+        let (ret, second) = testing::capture(toggle_passthrough);
+        ret.unwrap();
+        assert_eq!(first, ["enter-mode passthrough"]);
+        assert_eq!(second, ["enter-mode normal"]);
+    }
+
+    #[test]
+    fn message_commands_per_level() {
+        let (ret, commands) = testing::capture(|| {
+            message_info("info")?;
+            message_warning("two\nlines")?;
+            message_error("a ;; b")
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "message-info info",
+                "message-warning two lines",
+                "message-error a ; ; b",
+            ]
+        );
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn extract_links_resolves_relative_links() {
+        let dir = testing::temp_dir("util-extract-links");
+        let path = dir.join("page.html");
+        fs::write(
+            &path,
+            r#"<a href="https://other.example/">abs</a> <a href="../up">rel</a>"#,
+        )
+        .unwrap();
+        let _env = testing::set_env(&[
+            ("QUTE_HTML", path.to_str()),
+            ("QUTE_URL", Some("https://example.com/a/b/page")),
+        ]);
+
+        assert_eq!(
+            extract_links().unwrap(),
+            [
+                Link {
+                    href: "https://other.example/".into(),
+                    text: "abs".into(),
+                },
+                Link {
+                    href: "https://example.com/a/up".into(),
+                    text: "rel".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn private_window_commands() {
+        let (ret, commands) = testing::capture(|| {
+            open_private("https://example.com/")?;
+            new_private_window()
+        });
+        ret.unwrap();
+        assert_eq!(commands, ["open -p https://example.com/", "open -p"]);
+    }
+
+    #[test]
+    fn reader_mode_spawns_readability() {
+        let (ret, commands) = testing::capture(reader_mode);
+        ret.unwrap();
+        assert_eq!(commands, ["spawn --userscript readability"]);
+    }
+
+    #[test]
+    fn fake_key_page_and_global() {
+        let (ret, commands) = testing::capture(|| {
+            fake_key("<Escape>", false)?;
+            fake_key_global("<Ctrl-t>")
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            ["fake-key <Escape>", "fake-key --global <Ctrl-t>"]
+        );
+
+        let (ret, commands) = testing::capture(|| fake_key("<Ctrl-t", false));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn open_related_commands() {
+        let (ret, commands) = testing::capture(|| {
+            open_related("https://example.com/", false)?;
+            open_related("https://example.com/", true)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "open -r https://example.com/",
+                "open -t -r https://example.com/",
+            ]
+        );
+    }
+
+    #[test]
+    fn qute_urls() {
+        assert_eq!(QuteUrl::settings().as_str(), "qute://settings");
+        assert_eq!(QuteUrl::history().as_str(), "qute://history");
+        assert_eq!(QuteUrl::bookmarks().as_str(), "qute://bookmarks");
+        assert_eq!(
+            QuteUrl::help("commands.html#open").as_str(),
+            "qute://help/commands.html#open"
+        );
+        assert_eq!(
+            QuteUrl::help("user guide/ä.html#a b").to_string(),
+            "qute://help/user%20guide/%C3%A4.html#a%20b"
+        );
+        assert_eq!(String::from(QuteUrl::settings()), "qute://settings");
+    }
+
+    #[test]
+    fn edit_current_url_in_cmdline_prefills_open() {
+        let _env = testing::set_env(&[("QUTE_URL", Some("https://example.com/?q=a b"))]);
+        let (ret, commands) = testing::capture(edit_current_url_in_cmdline);
+        ret.unwrap();
+        assert_eq!(commands, ["cmd-set-text :open https://example.com/?q=a b"]);
+    }
+
+    #[cfg(feature = "percent-encoding")]
+    #[test]
+    fn search_selection_opens_search() {
+        let _env = testing::set_env(&[("QUTE_SELECTED_TEXT", Some("rust & qutebrowser"))]);
+        let (ret, commands) = testing::capture(|| {
+            search_selection("https://duckduckgo.com/?q={}", true)?;
+            search_selection("https://example.com/search/", false)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "open -t https://duckduckgo.com/?q=rust%20%26%20qutebrowser",
+                "open https://example.com/search/rust%20%26%20qutebrowser",
+            ]
+        );
+    }
+
+    #[cfg(feature = "percent-encoding")]
+    #[test]
+    fn search_selection_warns_without_selection() {
+        let _env = testing::set_env(&[("QUTE_SELECTED_TEXT", Some("  "))]);
+        let (ret, commands) = testing::capture(|| search_selection("https://example.com/", true));
+        ret.unwrap();
+        assert_eq!(commands, ["message-warning No text selected to search for"]);
+    }
+
+    #[test]
+    fn enter_mode_rejects_command_mode() {
+        assert!(Mode::Insert.is_enterable());
+        assert!(!Mode::Command.is_enterable());
+
+        let (ret, commands) = testing::capture(|| enter_mode(Mode::Insert));
+        ret.unwrap();
+        assert_eq!(commands, ["enter-mode insert"]);
+
+        let (ret, commands) = testing::capture(|| enter_mode(Mode::Command));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn set_clipboard_yanks_inline() {
+        let (ret, commands) = testing::capture(|| {
+            set_clipboard("some \"text\"\nhere", ClipboardSel::Clipboard)?;
+            set_clipboard("a ;; b", ClipboardSel::Primary)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "yank inline some \"text\" here",
+                "yank --sel inline a ; ; b",
+            ]
+        );
+    }
+
+    #[test]
+    fn hint_run_quotes_command() {
+        let (ret, commands) = testing::capture(|| {
+            hint_run(HintGroup::Links, r#"mpv --title "Hinted video" {hint-url}"#)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [r#"hint links spawn "mpv --title \"Hinted video\" {hint-url}""#]
+        );
+    }
+
+    #[test]
+    fn message_info_multi_joins_lines() {
+        let (ret, commands) =
+            testing::capture(|| message_info_multi(&[" first ", "", "second\nline", "a;;b"]));
+        ret.unwrap();
+        assert_eq!(commands, ["message-info first | second line | a; ;b"]);
+    }
+
+    #[test]
+    fn send_then_later_chains_followup() {
+        let (ret, commands) = testing::capture(|| {
+            send_then_later("open https://example.com/", 500, "scroll-to-perc 50")
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            ["open https://example.com/ ;; later 500 scroll-to-perc 50"]
+        );
+
+        let (ret, commands) = testing::capture(|| send_then_later("reload", 0, "a ;; b"));
+        ret.unwrap();
+        assert_eq!(commands, ["reload ;; later 0 a ; ; b"]);
+    }
+
+    #[test]
+    fn escape_arg_quotes_and_breaks_up_separators() {
+        assert_eq!(escape_arg("plain"), "plain");
+        assert_eq!(escape_arg(""), r#""""#);
+        assert_eq!(escape_arg(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(escape_arg("two\nlines"), r#""two lines""#);
+        assert_eq!(escape_arg("a;;b"), r#""a; ;b""#);
+        assert_eq!(escape_arg(";;;"), r#""; ; ;""#);
+        assert_eq!(escape_arg(";;;;"), r#""; ; ; ;""#);
+        assert_eq!(escape_rest("a;;;open x"), "a; ; ;open x");
+        assert!(!escape_rest(";;;;;").contains(";;"));
+    }
+
+    #[test]
+    fn insert_text_commands() {
+        let (ret, commands) = testing::capture(|| {
+            insert_text("two\nlines ;; here")?;
+            insert_text_with_cursor("()", 1)?;
+            insert_text_with_cursor("end", 0)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "insert-text two lines ; ; here",
+                "insert-text () ;; fake-key <Left>",
+                "insert-text end",
+            ]
+        );
+
+        let (ret, commands) = testing::capture(|| insert_text_with_cursor("a;;b", 1));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn open_command_escapes_url() {
+        assert_eq!(
+            Open::new().tab(true).count(Some(2)).command("a\nb ;; c"),
+            "2open -t a b ; ; c"
+        );
+        assert_eq!(Open::new().window(true).command(""), "open -w");
+    }
+
+    #[test]
+    fn search_highlight_jumps_back() {
+        let (ret, commands) = testing::capture(|| {
+            search_highlight("needle")?;
+            search_highlight("x ;; open https://evil\nmore")
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                r#"search needle ;; jump-mark "'""#,
+                r#"search x ; ; open https://evil more ;; jump-mark "'""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn yank_inline_escapes_text() {
+        let (ret, commands) = testing::capture(|| {
+            yank(Yank::Inline("x ;; open https://evil\nsecond".into()), false)?;
+            yank(Yank::Url, true)
+        });
+        ret.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "yank inline x ; ; open https://evil second",
+                "yank --sel url"
+            ]
+        );
+    }
+
+    #[test]
+    fn report_on_error_without_fifo() {
+        let _env = testing::set_env(&[("QUTE_FIFO", None)]);
+        let (ret, commands) =
+            testing::capture(|| report_on_error(Err::<(), _>(io::Error::other("no fifo"))));
+        assert_eq!(ret.unwrap_err().to_string(), "no fifo");
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn tab_focus_perc_rounds_to_nearest_tab() {
+        let _env = testing::autosave("util-tab-focus-perc", testing::SESSION);
+
+        // The focused window has five tabs, so each tab covers 25 percent.
+        let expected = [
+            (0, "tab-focus 1"),
+            (12, "tab-focus 1"),
+            (13, "tab-focus 2"),
+            (37, "tab-focus 2"),
+            (38, "tab-focus 3"),
+            (50, "tab-focus 3"),
+            (87, "tab-focus 4"),
+            (88, "tab-focus 5"),
+            (100, "tab-focus 5"),
+        ];
+        for &(perc, command) in &expected {
+            let (ret, commands) = testing::capture(|| tab_focus_perc(perc));
+            ret.unwrap();
+            assert_eq!(commands, [command], "{} percent", perc);
+        }
+
+        let (ret, commands) = testing::capture(|| tab_focus_perc(101));
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+    }
+
+    /// Returns a session with one window whose tabs are `(pinned, active)`.
+    fn session_with_tabs(tabs: &[(bool, bool)]) -> String {
+        let mut session = String::from("windows:\n- active: true\n  tabs:\n");
+        for (i, &(pinned, active)) in tabs.iter().enumerate() {
+            session.push_str(&format!(
+                "  - active: {}\n    history:\n    - pinned: {}\n      url: https://{}.example/\n",
+                active, pinned, i
+            ));
+        }
+        session
+    }
+
+    #[test]
+    fn tab_next_and_prev_unpinned_skip_pinned_tabs() {
+        // Tabs: pinned, unpinned, focused, pinned, unpinned.
+        let _env = testing::autosave("util-tab-unpinned", testing::SESSION);
+
+        let (ret, commands) = testing::capture(tab_next_unpinned);
+        ret.unwrap();
+        assert_eq!(commands, ["tab-focus 5"]);
+
+        let (ret, commands) = testing::capture(tab_prev_unpinned);
+        ret.unwrap();
+        assert_eq!(commands, ["tab-focus 2"]);
+    }
+
+    #[test]
+    fn tab_next_and_prev_unpinned_wrap_around() {
+        let session = session_with_tabs(&[(false, false), (true, false), (false, true)]);
+        let env = testing::autosave("util-tab-unpinned-wrap-next", &session);
+        let (ret, commands) = testing::capture(tab_next_unpinned);
+        ret.unwrap();
+        assert_eq!(commands, ["tab-focus 1"]);
+        drop(env);
+
+        let session = session_with_tabs(&[(false, true), (true, false), (false, false)]);
+        let env = testing::autosave("util-tab-unpinned-wrap-prev", &session);
+        let (ret, commands) = testing::capture(tab_prev_unpinned);
+        ret.unwrap();
+        assert_eq!(commands, ["tab-focus 3"]);
+        drop(env);
+
+        let session = session_with_tabs(&[(true, false), (false, true), (true, false)]);
+        let _env = testing::autosave("util-tab-unpinned-none", &session);
+        for f in &[tab_next_unpinned, tab_prev_unpinned] {
+            let (ret, commands) = testing::capture(f);
+            ret.unwrap();
+            assert!(commands.is_empty());
+        }
+    }
 }
@@ -0,0 +1,293 @@
+//! A parser for the subset of YAML used in qutebrowser's session and `autoconfig.yml`
+//! files: block mappings and sequences, plain and quoted scalars, and block scalars.
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Scalar(String),
+    Seq(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Value::Scalar(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get_str(key) == Some("true")
+    }
+
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Value::Seq(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+struct Line {
+    indent: usize,
+    content: String,
+}
+
+pub fn parse(s: &str) -> Value {
+    let mut lines: Vec<_> = s
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim();
+            !trimmed.is_empty() && !trimmed.starts_with('#') && trimmed != "---"
+        })
+        .map(|line| {
+            let content = line.trim_start();
+            Line {
+                indent: line.len() - content.len(),
+                content: content.trim_end().into(),
+            }
+        })
+        .collect();
+
+    let mut idx = 0;
+    match lines.first().map(|line| line.indent) {
+        Some(indent) => parse_node(&mut lines, &mut idx, indent),
+        None => Value::Map(Vec::new()),
+    }
+}
+
+fn is_seq_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+fn parse_node(lines: &mut [Line], idx: &mut usize, indent: usize) -> Value {
+    if is_seq_item(&lines[*idx].content) {
+        parse_seq(lines, idx, indent)
+    } else {
+        parse_map(lines, idx, indent)
+    }
+}
+
+fn parse_seq(lines: &mut [Line], idx: &mut usize, indent: usize) -> Value {
+    let mut items = Vec::new();
+    while *idx < lines.len() && lines[*idx].indent == indent && is_seq_item(&lines[*idx].content) {
+        let rest = lines[*idx].content[1..].trim_start().to_string();
+        if rest.is_empty() {
+            *idx += 1;
+            match lines.get(*idx) {
+                Some(next) if next.indent > indent => {
+                    let child_indent = next.indent;
+                    items.push(parse_node(lines, idx, child_indent));
+                }
+                _ => items.push(Value::Scalar(String::new())),
+            }
+        } else if is_scalar_item(&rest) {
+            items.push(Value::Scalar(parse_scalar(&rest)));
+            *idx += 1;
+        } else {
+            // Treat the rest of the line as the first line of a node nested under the
+            // item, e.g. the first key of a mapping in `- key: value`.
+            let item_indent = indent + (lines[*idx].content.len() - rest.len());
+            lines[*idx] = Line {
+                indent: item_indent,
+                content: rest,
+            };
+            items.push(parse_node(lines, idx, item_indent));
+        }
+    }
+    Value::Seq(items)
+}
+
+/// Whether the rest of a sequence item line is a scalar rather than the first line of a
+/// nested mapping or sequence.
+fn is_scalar_item(rest: &str) -> bool {
+    rest.starts_with('\'')
+        || rest.starts_with('"')
+        || !is_seq_item(rest) && split_key(rest).is_none()
+}
+
+fn parse_map(lines: &mut [Line], idx: &mut usize, indent: usize) -> Value {
+    let mut entries = Vec::new();
+    while *idx < lines.len() && lines[*idx].indent >= indent {
+        if lines[*idx].indent > indent || is_seq_item(&lines[*idx].content) {
+            // Not part of this mapping; skip it.
+            *idx += 1;
+            continue;
+        }
+
+        let content = lines[*idx].content.clone();
+        let (key, value) = match split_key(&content) {
+            Some(kv) => kv,
+            None => {
+                *idx += 1;
+                continue;
+            }
+        };
+        *idx += 1;
+
+        let value = if value.is_empty() {
+            match lines.get(*idx) {
+                Some(next) if next.indent > indent => {
+                    let child_indent = next.indent;
+                    parse_node(lines, idx, child_indent)
+                }
+                // Sequences may be at the same indentation as their key.
+                Some(next) if next.indent == indent && is_seq_item(&next.content) => {
+                    parse_seq(lines, idx, indent)
+                }
+                _ => Value::Scalar(String::new()),
+            }
+        } else {
+            let mut raw = value.to_string();
+            let block = is_block_scalar(&raw);
+            let mut continuation = Vec::new();
+            while *idx < lines.len() && lines[*idx].indent > indent {
+                continuation.push(lines[*idx].content.as_str());
+                *idx += 1;
+            }
+            if block {
+                Value::Scalar(continuation.join("\n"))
+            } else {
+                for line in continuation {
+                    raw.push(' ');
+                    raw.push_str(line);
+                }
+                Value::Scalar(parse_scalar(&raw))
+            }
+        };
+        entries.push((unquote_key(key), value));
+    }
+    Value::Map(entries)
+}
+
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    if let Some(key) = content.strip_suffix(':') {
+        return Some((key, ""));
+    }
+    content
+        .find(": ")
+        .map(|i| (&content[..i], content[i + 2..].trim_start()))
+}
+
+fn unquote_key(key: &str) -> String {
+    if key.starts_with('\'') || key.starts_with('"') {
+        parse_scalar(key)
+    } else {
+        key.into()
+    }
+}
+
+fn is_block_scalar(value: &str) -> bool {
+    let value = value.strip_prefix("!!binary").unwrap_or(value).trim_start();
+    value.starts_with('|') || value.starts_with('>')
+}
+
+fn parse_scalar(raw: &str) -> String {
+    let raw = raw.trim();
+    if raw.len() >= 2 && raw.starts_with('\'') && raw.ends_with('\'') {
+        raw[1..raw.len() - 1].replace("''", "'")
+    } else if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        unescape_double_quoted(&raw[1..raw.len() - 1])
+    } else {
+        raw.into()
+    }
+}
+
+fn unescape_double_quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some(c @ ('x' | 'u' | 'U')) => {
+                let len = match c {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let hex: String = chars.by_ref().take(len).collect();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            }
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar(value: Option<&Value>) -> &str {
+        match value {
+            Some(Value::Scalar(s)) => s,
+            other => panic!("expected a scalar, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mappings_and_sequences() {
+        let root = parse(
+            "---\n\
+             # comment\n\
+             config_version: 2\n\
+             settings:\n  \
+               content.blocking.whitelist:\n    \
+                 global:\n    \
+                 - example.com\n    \
+                 - other.example\n  \
+               'quoted.key':\n    \
+                 global: true\n\
+             empty:\n",
+        );
+        assert_eq!(root.get_str("config_version"), Some("2"));
+        let settings = root.get("settings").unwrap();
+        let whitelist = settings
+            .get("content.blocking.whitelist")
+            .and_then(|option| option.get("global"))
+            .unwrap();
+        let items: Vec<_> = whitelist
+            .items()
+            .iter()
+            .map(|item| scalar(Some(item)))
+            .collect();
+        assert_eq!(items, ["example.com", "other.example"]);
+        assert!(settings.get("quoted.key").unwrap().get_bool("global"));
+        assert_eq!(root.get_str("empty"), Some(""));
+        assert!(root.get("missing").is_none());
+    }
+
+    #[test]
+    fn quoted_and_block_scalars() {
+        let root = parse(
+            "single: 'It''s here'\n\
+             double: \"tab\\there \\\"quoted\\\" \\u00e9\"\n\
+             plain: a plain\n  continued line\n\
+             block: |\n  first\n  second\n\
+             binary: !!binary |\n  AAEC\n  AwQF\n\
+             after: done\n",
+        );
+        assert_eq!(root.get_str("single"), Some("It's here"));
+        assert_eq!(root.get_str("double"), Some("tab\there \"quoted\" é"));
+        assert_eq!(root.get_str("plain"), Some("a plain continued line"));
+        assert_eq!(root.get_str("block"), Some("first\nsecond"));
+        assert_eq!(root.get_str("binary"), Some("AAEC\nAwQF"));
+        assert_eq!(root.get_str("after"), Some("done"));
+    }
+}